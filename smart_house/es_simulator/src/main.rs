@@ -1,125 +1,140 @@
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use async_trait::async_trait;
+use sdcp::device_error::DeviceError;
+use sdcp::events::SdcpNotifier;
 use sdcp::results::FrameResult;
+use sdcp::retry::RetryPolicy;
+use sdcp::udp::discovery::{DeviceInfo, DiscoveryResponder, SDCP_DISCOVERY_PORT, SDCP_TYPE_SOCKET};
 use sdcp::{
-    ParamItem, SdcpFrame, SdcpHandler, SDCP_COMMANDS, SDCP_FAILED, SDCP_OK, SDCP_PACKET_HEADER,
-    SDCP_PARAM_PWRCON, SDCP_PARAM_STATUS,
+    ParamItem, ParamValue, SdcpCommand, SdcpErrorCode, SdcpFrame, SdcpHandler, SdcpRequestHandler,
+    SdcpResult, SDCP_PACKET_HEADER, SDCP_PARAM_PWRCON, SDCP_PARAM_STATUS,
 };
 
+const SDCP_SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+const SDCP_BIND_RETRIES: u32 = 5;
+
+#[derive(Default)]
 struct ElectricSocket {
     power_consumption: u32,
     status: bool,
 }
 
-static mut ES: ElectricSocket = ElectricSocket {
-    power_consumption: 0,
-    status: false,
-};
+#[derive(Default)]
+struct ElectricSocketHandler {
+    state: Arc<Mutex<ElectricSocket>>,
+    notifier: SdcpNotifier,
+}
 
-fn handler(frame: FrameResult) -> SdcpFrame {
-    match frame {
-        Ok(frame) => {
-            println!("Protocol: {}", frame.protocol);
-            println!("Commnad: {}", frame.command);
-            println!("Result: {}", frame.result);
-            for item in frame.parameters.iter() {
-                println!("Parameter: {}={}", item.name, item.value);
-            }
-            let command = frame.command.as_str();
-            let mut response: SdcpFrame = SdcpFrame {
-                protocol: SDCP_PACKET_HEADER.to_string(),
-                command: command.to_string(),
-                parameters: vec![],
-                result: SDCP_OK.to_string(),
-            };
-            let err_response: SdcpFrame = SdcpFrame {
-                protocol: SDCP_PACKET_HEADER.to_string(),
-                command: command.to_string(),
-                parameters: vec![],
-                result: SDCP_FAILED.to_string(),
-            };
-            match command {
-                SDCP_COMMANDS::GETP => {
-                    for item in frame.parameters.iter() {
-                        let item_name = item.name.as_str();
-                        match item_name {
-                            SDCP_PARAM_STATUS => unsafe {
-                                response.parameters.push(ParamItem::new(
+#[async_trait]
+impl SdcpRequestHandler for ElectricSocketHandler {
+    async fn handle(&self, frame: FrameResult) -> SdcpFrame {
+        match frame {
+            Ok(frame) => {
+                println!("Protocol: {}", frame.protocol);
+                println!("Commnad: {}", frame.command);
+                println!("Result: {}", frame.result);
+                for item in frame.parameters.iter() {
+                    println!("Parameter: {}={}", item.name, item.value);
+                }
+                let mut response: SdcpFrame = SdcpFrame {
+                    protocol: SDCP_PACKET_HEADER.to_string(),
+                    id: None,
+                    command: frame.command.clone(),
+                    parameters: vec![],
+                    result: SdcpResult::Ok,
+                };
+                let mut error = DeviceError::new(SdcpErrorCode::InvalidValue, "");
+                match frame.command {
+                    SdcpCommand::Getp => {
+                        for item in frame.parameters.iter() {
+                            let item_name = item.name.as_str();
+                            match item_name {
+                                SDCP_PARAM_STATUS => response.parameters.push(ParamItem::new(
                                     item_name.to_string(),
-                                    ES.status.to_string(),
-                                ))
-                            },
-                            SDCP_PARAM_PWRCON => unsafe {
-                                response.parameters.push(ParamItem::new(
+                                    self.state.lock().unwrap().status.into(),
+                                )),
+                                SDCP_PARAM_PWRCON => response.parameters.push(ParamItem::new(
                                     item_name.to_string(),
-                                    ES.power_consumption.to_string(),
-                                ))
-                            },
-                            _ => response.parameters.push(ParamItem::new(
-                                item_name.to_string(),
-                                String::from("UNKNOWN"),
-                            )),
+                                    self.state.lock().unwrap().power_consumption.into(),
+                                )),
+                                _ => {
+                                    error.add_parameter(item_name, SdcpErrorCode::UnknownParameter)
+                                }
+                            }
                         }
                     }
-                    response
-                }
-                SDCP_COMMANDS::SETP => {
-                    for item in frame.parameters.iter() {
-                        let item_name = item.name.as_str();
-                        let item_value = item.value.as_str();
-                        match item_name {
-                            SDCP_PARAM_STATUS => {
-                                match bool::from_str(item_value.to_lowercase().as_str()) {
-                                    Ok(value) => {
-                                        unsafe {
-                                            ES.status = value;
+                    SdcpCommand::Setp => {
+                        // Every parameter is checked before any is applied, a failed request
+                        // changes nothing and notifies nobody
+                        for item in frame.parameters.iter() {
+                            let item_name = item.name.as_str();
+                            let code = match item_name {
+                                SDCP_PARAM_STATUS if matches!(item.value, ParamValue::Bool(_)) => {
+                                    continue
+                                }
+                                SDCP_PARAM_PWRCON
+                                    if matches!(
+                                        item.value.as_integer().map(u32::try_from),
+                                        Some(Ok(_))
+                                    ) =>
+                                {
+                                    continue
+                                }
+                                SDCP_PARAM_STATUS | SDCP_PARAM_PWRCON => {
+                                    SdcpErrorCode::InvalidValue
+                                }
+                                _ => SdcpErrorCode::UnknownParameter,
+                            };
+                            error.add_parameter(item_name, code);
+                        }
+                        if error.parameters.is_empty() {
+                            {
+                                let mut state = self.state.lock().unwrap();
+                                for item in frame.parameters.iter() {
+                                    match (item.name.as_str(), &item.value) {
+                                        (SDCP_PARAM_STATUS, ParamValue::Bool(value)) => {
+                                            state.status = *value
                                         }
-                                        response.parameters.push(ParamItem::new(
-                                            item_name.to_string(),
-                                            item_value.to_string(),
-                                        ))
-                                    }
-                                    Err(_) => {
-                                        return err_response;
+                                        (SDCP_PARAM_PWRCON, value) => {
+                                            if let Some(Ok(value)) =
+                                                value.as_integer().map(u32::try_from)
+                                            {
+                                                state.power_consumption = value
+                                            }
+                                        }
+                                        _ => (),
                                     }
                                 }
                             }
-                            SDCP_PARAM_PWRCON => match u32::from_str(item_value) {
-                                Ok(value) => {
-                                    unsafe {
-                                        ES.power_consumption = value;
-                                    }
-                                    response.parameters.push(ParamItem::new(
-                                        item_name.to_string(),
-                                        item_value.to_string(),
-                                    ))
-                                }
-                                Err(_) => {
-                                    println!("Value conversation failed");
-                                    return err_response;
-                                }
-                            },
-                            _ => response.parameters.push(ParamItem::new(
-                                item_name.to_string(),
-                                item_value.to_string(),
-                            )),
+                            for item in frame.parameters.iter() {
+                                self.notifier.notify(&item.name, item.value.clone());
+                                response.parameters.push(item.clone());
+                            }
                         }
                     }
-                    response
+                    SdcpCommand::Info => (),
+                    _ => {
+                        return DeviceError::new(
+                            SdcpErrorCode::UnsupportedCommand,
+                            "the socket supports GETP, SETP and INFO",
+                        )
+                        .frame(frame.command)
+                    }
+                }
+                // The first failed parameter decides the overall code
+                match error.parameters.first() {
+                    Some(parameter) => {
+                        error.code = parameter.code.clone();
+                        error.frame(frame.command)
+                    }
+                    None => response,
                 }
-                SDCP_COMMANDS::INFO => response,
-                _ => response,
             }
-        }
-        Err(_) => {
-            let err_response: SdcpFrame = SdcpFrame {
-                protocol: SDCP_PACKET_HEADER.to_string(),
-                command: SDCP_COMMANDS::NONE.to_string(),
-                parameters: vec![],
-                result: SDCP_FAILED.to_string(),
-            };
-            err_response
+            Err(error) => DeviceError::new(SdcpErrorCode::InvalidFrame, &error.to_string())
+                .frame(SdcpCommand::None),
         }
     }
 }
@@ -128,16 +143,75 @@ fn handler(frame: FrameResult) -> SdcpFrame {
 async fn main() {
     println!("Smart electric socket simulator");
 
-    let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 55100);
-    let sdcp = SdcpHandler::new(address);
-    sdcp.bind(handler).await;
+    // Several simulators can run side by side when each gets its own address and device name
+    let mut args = std::env::args();
+    let address = args
+        .nth(1)
+        .map(|address| address.parse::<SocketAddr>().expect("Неверный адрес"))
+        .unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 55100));
+    let device = args.next().unwrap_or_else(|| String::from("ES-1"));
+    println!("Устройство {} по адресу {}", device, address);
+
+    let mut sdcp = SdcpHandler::new(address);
+    sdcp.set_idle_timeout(Some(SDCP_SESSION_TIMEOUT));
+    sdcp.set_retry_policy(RetryPolicy::new(
+        SDCP_BIND_RETRIES,
+        Duration::from_millis(500),
+        Duration::from_secs(5),
+    ));
+    let handler = ElectricSocketHandler::default();
+    let state = Arc::clone(&handler.state);
+    let notifier = handler.notifier.clone();
+    sdcp.set_notifier(notifier.clone());
+    let mut server = match sdcp.bind(handler).await {
+        Ok(server) => server,
+        Err(error) => {
+            println!("Binding error: {}", error);
+            std::process::exit(1);
+        }
+    };
+    let info = DeviceInfo::new(
+        device,
+        SDCP_TYPE_SOCKET.to_string(),
+        server.local_address(),
+        vec![SDCP_PARAM_STATUS.to_string(), SDCP_PARAM_PWRCON.to_string()],
+    );
+    let discovery_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), SDCP_DISCOVERY_PORT);
+    let discovery = match DiscoveryResponder::bind(discovery_address, info).await {
+        Ok(discovery) => Some(discovery),
+        Err(error) => {
+            println!("Discovery is not available: {}", error);
+            None
+        }
+    };
+    if let Some(mut errors) = server.take_errors() {
+        tokio::spawn(async move {
+            while let Some(error) = errors.recv().await {
+                println!("Server error: {}", error);
+            }
+        });
+    }
 
     loop {
         let exit = String::from("exit");
         let mut buffer: String = String::new();
         std::io::stdin().read_line(&mut buffer).unwrap();
         if buffer.trim().eq(&exit) {
+            if let Some(discovery) = discovery {
+                discovery.shutdown().await;
+            }
+            server.shutdown().await;
             std::process::exit(0);
         }
+        // Emulates the socket being switched by hand, subscribed controllers learn about it
+        if buffer.trim().eq("switch") {
+            let status = {
+                let mut state = state.lock().unwrap();
+                state.status = !state.status;
+                state.status
+            };
+            println!("Status: {}", status);
+            notifier.notify(SDCP_PARAM_STATUS, status.into());
+        }
     }
 }
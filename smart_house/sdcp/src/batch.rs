@@ -0,0 +1,45 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::results::RequestResult;
+
+// Above the size of a house fleet, so every device is asked in one round trip
+pub const SDCP_BATCH_CONCURRENCY: usize = 64;
+// Covers every attempt of one item, so a hanging device cannot hold up the report
+pub const SDCP_BATCH_ITEM_DEADLINE: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub struct BatchItem {
+    pub address: SocketAddr,
+    pub result: RequestResult,
+}
+
+impl BatchItem {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+// Items keep the order of the requests they answer
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+}
+
+impl BatchReport {
+    pub fn new(items: Vec<BatchItem>) -> Self {
+        Self { items }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.items.iter().all(BatchItem::is_ok)
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.items.iter().filter(|item| item.is_ok()).count()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &BatchItem> {
+        self.items.iter().filter(|item| !item.is_ok())
+    }
+}
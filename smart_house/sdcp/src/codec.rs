@@ -0,0 +1,238 @@
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::encoding::{binary_packet_id, make_binary_frame, make_binary_packet};
+use crate::results::{FrameResult, RecvError, SendError};
+use crate::version::{SdcpVersion, SDCP_BASE_VERSION, SDCP_PROTOCOL};
+use crate::{
+    make_frame_with_case, make_packet, text_packet_id, PayloadEncoding, SdcpFrame, ValueCase,
+};
+
+pub const SDCP_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+// The base version header "SDCP 0.0.1" is followed directly by the length as baseline peers
+// send it, so the version ends at the first length byte which must be zero; newer versions
+// end the header with "\n" and can carry any length
+const SDCP_HEADER_DELIMITER: u8 = b'\n';
+const SDCP_BASE_FRAME_SIZE_LIMIT: usize = 0x00FF_FFFF;
+const SDCP_LENGTH_SIZE: usize = 4;
+const SDCP_MAX_VERSION_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SdcpCodec {
+    max_frame_size: usize,
+    version: SdcpVersion,
+}
+
+impl SdcpCodec {
+    pub fn new(max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size: max_frame_size.min(u32::MAX as usize),
+            version: SDCP_BASE_VERSION,
+        }
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn version(&self) -> SdcpVersion {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: SdcpVersion) {
+        self.version = version;
+    }
+}
+
+// Returns the header version and length, or None while the header is incomplete
+fn decode_header(src: &[u8]) -> Result<Option<(SdcpVersion, usize)>, RecvError> {
+    let prefix = format!("{} ", SDCP_PROTOCOL);
+    let prefix_length = src.len().min(prefix.len());
+    if !src[..prefix_length].eq(&prefix.as_bytes()[..prefix_length]) {
+        return Err(RecvError::InvalidPacket);
+    }
+    if src.len() < prefix.len() {
+        return Ok(None);
+    }
+    let version_bytes = &src[prefix.len()..];
+    let end = match version_bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_digit() && *byte != b'.')
+    {
+        Some(end) if end <= SDCP_MAX_VERSION_LENGTH => end,
+        Some(_) => return Err(RecvError::InvalidPacket),
+        None if version_bytes.len() > SDCP_MAX_VERSION_LENGTH => {
+            return Err(RecvError::InvalidPacket)
+        }
+        None => return Ok(None),
+    };
+    let version = std::str::from_utf8(&version_bytes[..end])
+        .map_err(|_| RecvError::InvalidPacket)?
+        .parse()
+        .map_err(|_| RecvError::InvalidPacket)?;
+    if !is_delimited(&version) {
+        return Ok(Some((version, prefix.len() + end)));
+    }
+    if version_bytes[end] != SDCP_HEADER_DELIMITER {
+        return Err(RecvError::InvalidPacket);
+    }
+    Ok(Some((version, prefix.len() + end + 1)))
+}
+
+fn is_delimited(version: &SdcpVersion) -> bool {
+    *version > SDCP_BASE_VERSION
+}
+
+impl Default for SdcpCodec {
+    fn default() -> Self {
+        Self::new(SDCP_MAX_FRAME_SIZE)
+    }
+}
+
+impl SdcpCodec {
+    fn decode_payload(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<(SdcpVersion, BytesMut)>, RecvError> {
+        let (version, header_length) = match decode_header(src)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if !version.is_compatible(&self.version) {
+            return Err(RecvError::UnsupportedVersion(version));
+        }
+        let prefix_length = header_length + SDCP_LENGTH_SIZE;
+        if src.len() < prefix_length {
+            src.reserve(prefix_length - src.len());
+            return Ok(None);
+        }
+        let mut length_bytes = [0; SDCP_LENGTH_SIZE];
+        length_bytes.copy_from_slice(&src[header_length..prefix_length]);
+        let data_length = u32::from_be_bytes(length_bytes) as usize;
+        if data_length > self.max_frame_size {
+            return Err(RecvError::FrameTooLarge(data_length));
+        }
+        if src.len() < prefix_length + data_length {
+            src.reserve(prefix_length + data_length - src.len());
+            return Ok(None);
+        }
+        src.advance(prefix_length);
+        Ok(Some((version, src.split_to(data_length))))
+    }
+
+    fn encode_payload(&mut self, data_bytes: &[u8], dst: &mut BytesMut) -> Result<(), SendError> {
+        if data_bytes.len() > self.max_frame_size {
+            return Err(SendError::FrameTooLarge(data_bytes.len()));
+        }
+        let delimited = is_delimited(&self.version);
+        if !delimited && data_bytes.len() > SDCP_BASE_FRAME_SIZE_LIMIT {
+            return Err(SendError::FrameTooLarge(data_bytes.len()));
+        }
+        let header = self.version.header();
+        dst.reserve(header.len() + 1 + SDCP_LENGTH_SIZE + data_bytes.len());
+        dst.put_slice(header.as_bytes());
+        if delimited {
+            dst.put_u8(SDCP_HEADER_DELIMITER);
+        }
+        dst.put_u32(data_bytes.len() as u32);
+        dst.put_slice(data_bytes);
+        Ok(())
+    }
+}
+
+impl Decoder for SdcpCodec {
+    type Item = String;
+    type Error = RecvError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<String>, RecvError> {
+        match self.decode_payload(src)? {
+            Some((_, data)) => String::from_utf8(data.to_vec())
+                .map(Some)
+                .map_err(|_| RecvError::BadEncoding),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<D: AsRef<str>> Encoder<D> for SdcpCodec {
+    type Error = SendError;
+
+    fn encode(&mut self, data: D, dst: &mut BytesMut) -> Result<(), SendError> {
+        self.encode_payload(data.as_ref().as_bytes(), dst)
+    }
+}
+
+// Turns payloads into frames using the text or binary encoding chosen for the connection
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SdcpFrameCodec {
+    codec: SdcpCodec,
+    encoding: PayloadEncoding,
+    value_case: ValueCase,
+}
+
+impl SdcpFrameCodec {
+    pub fn new(codec: SdcpCodec, encoding: PayloadEncoding, value_case: ValueCase) -> Self {
+        Self {
+            codec,
+            encoding,
+            value_case,
+        }
+    }
+
+    pub fn version(&self) -> SdcpVersion {
+        self.codec.version()
+    }
+
+    pub fn set_version(&mut self, version: SdcpVersion) {
+        self.codec.set_version(version);
+    }
+
+    pub fn encoding(&self) -> PayloadEncoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: PayloadEncoding) {
+        self.encoding = encoding;
+    }
+}
+
+impl Decoder for SdcpFrameCodec {
+    type Item = FrameResult;
+    type Error = RecvError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<FrameResult>, RecvError> {
+        let (version, data) = match self.codec.decode_payload(src)? {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+        let frame = match self.encoding {
+            PayloadEncoding::Text => {
+                let data = String::from_utf8(data.to_vec()).map_err(|_| RecvError::BadEncoding)?;
+                let id = text_packet_id(&data);
+                make_frame_with_case(data, self.value_case)
+                    .map_err(|error| error.with_request_id(id))
+            }
+            PayloadEncoding::Binary => make_binary_frame(&data, self.value_case)
+                .map_err(|error| error.with_request_id(binary_packet_id(&data))),
+        };
+        // The header tells which version the peer actually used for this frame
+        Ok(Some(frame.map(|frame| SdcpFrame {
+            protocol: version.header(),
+            ..frame
+        })))
+    }
+}
+
+impl Encoder<SdcpFrame> for SdcpFrameCodec {
+    type Error = SendError;
+
+    fn encode(&mut self, frame: SdcpFrame, dst: &mut BytesMut) -> Result<(), SendError> {
+        match self.encoding {
+            PayloadEncoding::Text => self
+                .codec
+                .encode_payload(make_packet(frame).as_bytes(), dst),
+            PayloadEncoding::Binary => self.codec.encode_payload(&make_binary_packet(frame), dst),
+        }
+    }
+}
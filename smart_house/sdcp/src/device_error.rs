@@ -0,0 +1,130 @@
+use crate::results::{RequestError, RequestResult};
+use crate::{
+    ParamItem, ParamValue, SdcpCommand, SdcpErrorCode, SdcpFrame, SdcpResult, SDCP_PACKET_HEADER,
+    SDCP_PARAM_ERROR, SDCP_PARAM_MESSAGE,
+};
+
+// Per-parameter codes travel as ERROR.<NAME>=<CODE>
+const SDCP_PARAM_ERROR_SEPARATOR: char = '.';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamError {
+    pub name: String,
+    pub code: SdcpErrorCode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceError {
+    pub code: SdcpErrorCode,
+    pub message: String,
+    pub parameters: Vec<ParamError>,
+}
+
+impl DeviceError {
+    pub fn new(code: SdcpErrorCode, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            parameters: vec![],
+        }
+    }
+
+    pub fn add_parameter(&mut self, name: &str, code: SdcpErrorCode) {
+        self.parameters.push(ParamError {
+            name: name.to_uppercase(),
+            code,
+        });
+    }
+
+    pub fn frame(&self, command: SdcpCommand) -> SdcpFrame {
+        let mut parameters = vec![
+            ParamItem::new(
+                SDCP_PARAM_ERROR.to_string(),
+                ParamValue::String(self.code.to_string()),
+            ),
+            ParamItem::new(
+                SDCP_PARAM_MESSAGE.to_string(),
+                ParamValue::String(self.message.clone()),
+            ),
+        ];
+        parameters.extend(self.parameters.iter().map(|parameter| {
+            ParamItem::new(
+                format!(
+                    "{}{}{}",
+                    SDCP_PARAM_ERROR, SDCP_PARAM_ERROR_SEPARATOR, parameter.name
+                ),
+                ParamValue::String(parameter.code.to_string()),
+            )
+        }));
+        SdcpFrame {
+            protocol: SDCP_PACKET_HEADER.to_string(),
+            id: None,
+            command,
+            parameters,
+            result: SdcpResult::Failed,
+        }
+    }
+
+    // Error codes and messages are the device's own text, the value case does not touch them
+    pub(crate) fn keeps_case(name: &str) -> bool {
+        let name = name.to_uppercase();
+        name.eq(SDCP_PARAM_ERROR)
+            || name.eq(SDCP_PARAM_MESSAGE)
+            || name
+                .strip_prefix(SDCP_PARAM_ERROR)
+                .is_some_and(|name| name.starts_with(SDCP_PARAM_ERROR_SEPARATOR))
+    }
+
+    // Every FAILED frame is an error, peers without error codes give an unspecified one
+    pub fn from_frame(frame: &SdcpFrame) -> Option<Self> {
+        if frame.result != SdcpResult::Failed {
+            return None;
+        }
+        let mut error = Self::new(SdcpErrorCode::Unspecified, "");
+        for item in frame.parameters.iter() {
+            let code = item.value.to_string();
+            let Ok(parsed) = code.parse();
+            if item.name.eq(SDCP_PARAM_ERROR) {
+                error.code = parsed;
+            } else if item.name.eq(SDCP_PARAM_MESSAGE) {
+                error.message = code;
+            } else if let Some(name) = item
+                .name
+                .strip_prefix(SDCP_PARAM_ERROR)
+                .and_then(|name| name.strip_prefix(SDCP_PARAM_ERROR_SEPARATOR))
+            {
+                error.add_parameter(name, parsed);
+            }
+        }
+        Some(error)
+    }
+
+    fn describe(&self) -> String {
+        let parameters = self
+            .parameters
+            .iter()
+            .map(|parameter| format!("{}: {}", parameter.name, parameter.code))
+            .collect::<Vec<String>>();
+        match (self.message.is_empty(), parameters.is_empty()) {
+            (_, true) => self.message.clone(),
+            (true, false) => parameters.join(", "),
+            (false, false) => format!("{} ({})", self.message, parameters.join(", ")),
+        }
+    }
+}
+
+impl From<DeviceError> for RequestError {
+    fn from(error: DeviceError) -> Self {
+        Self::Device {
+            message: error.describe(),
+            code: error.code,
+        }
+    }
+}
+
+pub(crate) fn check_response(frame: SdcpFrame) -> RequestResult {
+    match DeviceError::from_frame(&frame) {
+        Some(error) => Err(error.into()),
+        None => Ok(frame),
+    }
+}
@@ -0,0 +1,136 @@
+use bytes::BufMut;
+use std::str;
+
+use crate::device_error::DeviceError;
+use crate::results::{FrameError, FrameResult};
+use crate::{
+    check_frame, ParamItem, ParamValue, SdcpCommand, SdcpFrame, SdcpResult, ValueCase,
+    SDCP_PACKET_HEADER,
+};
+
+// Every field is a tag byte and a big-endian u32 length followed by the field body
+const TAG_ID: u8 = 1;
+const TAG_COMMAND: u8 = 2;
+const TAG_RESULT: u8 = 3;
+const TAG_PARAMETER: u8 = 4;
+
+// A parameter body is the name length, the name, the value type and the value
+const VALUE_BOOL: u8 = 0;
+const VALUE_INTEGER: u8 = 1;
+const VALUE_FLOAT: u8 = 2;
+const VALUE_STRING: u8 = 3;
+
+fn put_field(data: &mut Vec<u8>, tag: u8, body: &[u8]) {
+    data.put_u8(tag);
+    data.put_u32(body.len() as u32);
+    data.put_slice(body);
+}
+
+pub fn make_binary_packet(frame: SdcpFrame) -> Vec<u8> {
+    let mut data = vec![];
+
+    if let Some(id) = frame.id {
+        put_field(&mut data, TAG_ID, &id.to_be_bytes());
+    }
+    put_field(&mut data, TAG_COMMAND, frame.command.to_string().as_bytes());
+    put_field(&mut data, TAG_RESULT, frame.result.to_string().as_bytes());
+    for parameter in frame.parameters.iter() {
+        let mut body = vec![];
+        body.put_u32(parameter.name.len() as u32);
+        body.put_slice(parameter.name.as_bytes());
+        match &parameter.value {
+            ParamValue::Bool(value) => {
+                body.put_u8(VALUE_BOOL);
+                body.put_u8(*value as u8);
+            }
+            ParamValue::Integer(value) => {
+                body.put_u8(VALUE_INTEGER);
+                body.put_i64(*value);
+            }
+            ParamValue::Float(value) => {
+                body.put_u8(VALUE_FLOAT);
+                body.put_f64(*value);
+            }
+            ParamValue::String(value) => {
+                body.put_u8(VALUE_STRING);
+                body.put_slice(value.as_bytes());
+            }
+        }
+        put_field(&mut data, TAG_PARAMETER, &body);
+    }
+
+    data
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Result<&'a [u8], FrameError> {
+    if data.len() < length {
+        return Err(FrameError::InvalidPacket);
+    }
+    let (head, tail) = data.split_at(length);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N], FrameError> {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(take(data, N)?);
+    Ok(bytes)
+}
+
+fn to_str(data: &[u8]) -> Result<&str, FrameError> {
+    str::from_utf8(data).map_err(|_| FrameError::InvalidPacket)
+}
+
+fn read_parameter(mut body: &[u8], value_case: ValueCase) -> Result<ParamItem, FrameError> {
+    let name_length = u32::from_be_bytes(take_array(&mut body)?) as usize;
+    let name = to_str(take(&mut body, name_length)?)?.to_uppercase();
+    let value = match take_array::<1>(&mut body)?[0] {
+        VALUE_BOOL => ParamValue::Bool(take_array::<1>(&mut body)?[0] != 0),
+        VALUE_INTEGER => ParamValue::Integer(i64::from_be_bytes(take_array(&mut body)?)),
+        VALUE_FLOAT => ParamValue::Float(f64::from_be_bytes(take_array(&mut body)?)),
+        VALUE_STRING if DeviceError::keeps_case(&name) => ParamValue::from(to_str(body)?),
+        VALUE_STRING => ParamValue::String(value_case.apply(to_str(body)?)),
+        _ => return Err(FrameError::InvalidPacket),
+    };
+    Ok(ParamItem::new(name, value))
+}
+
+pub fn make_binary_frame(mut data: &[u8], value_case: ValueCase) -> FrameResult {
+    let mut frame: SdcpFrame = SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::None,
+        parameters: vec![],
+        result: SdcpResult::Ok,
+    };
+    while !data.is_empty() {
+        let tag = take_array::<1>(&mut data)?[0];
+        let length = u32::from_be_bytes(take_array(&mut data)?) as usize;
+        let body = take(&mut data, length)?;
+        match tag {
+            TAG_ID => {
+                let id = body.try_into().map_err(|_| FrameError::InvalidPacket)?;
+                frame.id = Some(u32::from_be_bytes(id));
+            }
+            TAG_COMMAND => frame.command = to_str(body)?.parse().unwrap(),
+            TAG_RESULT => frame.result = to_str(body)?.parse().unwrap(),
+            TAG_PARAMETER => frame.parameters.push(read_parameter(body, value_case)?),
+            // Fields added by newer peers are skipped
+            _ => (),
+        }
+    }
+    check_frame(frame)
+}
+
+// Finds the id of a packet that cannot be read as a whole
+pub(crate) fn binary_packet_id(mut data: &[u8]) -> Option<u32> {
+    while !data.is_empty() {
+        let tag = take_array::<1>(&mut data).ok()?[0];
+        let length = u32::from_be_bytes(take_array(&mut data).ok()?) as usize;
+        let body = take(&mut data, length).ok()?;
+        if tag == TAG_ID {
+            return body.try_into().ok().map(u32::from_be_bytes);
+        }
+    }
+    None
+}
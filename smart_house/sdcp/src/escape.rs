@@ -0,0 +1,49 @@
+use crate::results::EscapeError;
+
+const ESCAPE: char = '\\';
+
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ESCAPE | ';' | '=') {
+            escaped.push(ESCAPE);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Escapes the first character as well, so the key is never read as one of the frame's own
+pub fn escape_key(value: &str) -> String {
+    format!("{ESCAPE}{}", escape(value))
+}
+
+pub fn unescape(value: &str) -> Result<String, EscapeError> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == ESCAPE {
+            unescaped.push(chars.next().ok_or(EscapeError)?);
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Ok(unescaped)
+}
+
+// Splits on separators that are not escaped, leaving escape sequences in place
+pub fn split_unescaped(value: &str, separator: char) -> Result<Vec<&str>, EscapeError> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut chars = value.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if c == ESCAPE {
+            chars.next().ok_or(EscapeError)?;
+        } else if c == separator {
+            parts.push(&value[start..index]);
+            start = index + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    Ok(parts)
+}
@@ -0,0 +1,117 @@
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::{ParamItem, ParamValue, SdcpCommand, SdcpFrame, SdcpResult, SDCP_PACKET_HEADER};
+
+pub const SDCP_EVENTS_CAPACITY: usize = 64;
+
+// Shared by the device and every session of its server; sessions forward the changes
+// their peer subscribed to as NOTI frames
+#[derive(Debug, Clone)]
+pub struct SdcpNotifier {
+    values: Arc<Mutex<HashMap<String, ParamValue>>>,
+    events: broadcast::Sender<ParamItem>,
+}
+
+impl SdcpNotifier {
+    pub fn new() -> Self {
+        Self {
+            values: Arc::new(Mutex::new(HashMap::new())),
+            events: broadcast::channel(SDCP_EVENTS_CAPACITY).0,
+        }
+    }
+
+    // Only a value that differs from the last notified one is a change
+    pub fn notify(&self, name: &str, value: ParamValue) -> bool {
+        let name = name.to_uppercase();
+        let mut values = self.values.lock().unwrap();
+        if values.get(&name).is_some_and(|last| last.eq(&value)) {
+            return false;
+        }
+        values.insert(name.clone(), value.clone());
+        let _ = self.events.send(ParamItem::new(name, value));
+        true
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ParamItem> {
+        self.events.subscribe()
+    }
+}
+
+impl Default for SdcpNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Subscriptions {
+    names: HashSet<String>,
+}
+
+impl Subscriptions {
+    pub(crate) fn subscribe(&mut self, frame: &SdcpFrame) {
+        self.names
+            .extend(frame.parameters.iter().map(|item| item.name.clone()));
+    }
+
+    // UNSU without parameters drops every subscription of the session
+    pub(crate) fn unsubscribe(&mut self, frame: &SdcpFrame) {
+        if frame.parameters.is_empty() {
+            self.names.clear();
+        }
+        for item in frame.parameters.iter() {
+            self.names.remove(&item.name);
+        }
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
+pub(crate) fn notify_frame(item: ParamItem) -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::Noti,
+        parameters: vec![item],
+        result: SdcpResult::Ok,
+    }
+}
+
+// Waits for the next change, forever when there is no notifier or it is gone
+pub(crate) async fn next_event(events: &mut Option<broadcast::Receiver<ParamItem>>) -> ParamItem {
+    if let Some(receiver) = events.as_mut() {
+        loop {
+            match receiver.recv().await {
+                Ok(item) => return item,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+    *events = None;
+    std::future::pending().await
+}
+
+// Ends when the session closes, a consumer that falls behind skips the changes it missed
+pub(crate) fn event_stream(
+    events: Option<broadcast::Receiver<ParamItem>>,
+) -> BoxStream<'static, ParamItem> {
+    match events {
+        Some(events) => stream::unfold(events, |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(item) => return Some((item, events)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed(),
+        None => stream::empty().boxed(),
+    }
+}
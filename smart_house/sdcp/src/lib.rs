@@ -1,58 +1,107 @@
+use async_trait::async_trait;
+use futures::{stream, Sink, SinkExt, Stream, StreamExt};
 use std::fmt::Write;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::str;
-use tokio::io::AsyncWriteExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tokio_util::codec::Framed;
+use tokio_util::sync::CancellationToken;
 
+use crate::batch::{BatchItem, BatchReport, SDCP_BATCH_CONCURRENCY, SDCP_BATCH_ITEM_DEADLINE};
+use crate::codec::SdcpCodec;
+use crate::device_error::{check_response, DeviceError};
+use crate::escape::{escape, escape_key, split_unescaped, unescape};
+use crate::events::SdcpNotifier;
+#[cfg(feature = "tls")]
+use crate::results::SessionError;
 use crate::results::{
-    FrameError, FrameResult, RecvError, RecvResult, RequestError, RequestResult, SendResult,
+    ConnectResult, FrameError, FrameResult, RecvError, RequestError, RequestResult, SendError,
+    SendResult, ServerError,
 };
+use crate::retry::{is_transient, retry, RetryPolicy};
+use crate::session::{handshake, serve_session, with_timeout, SessionOptions};
+#[cfg(feature = "tls")]
+use crate::tls::{TlsClient, TlsServer};
 
+pub mod batch;
+pub mod codec;
+pub mod device_error;
+pub mod encoding;
+pub mod escape;
+pub mod events;
 pub mod results;
+pub mod retry;
+pub mod session;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod types;
+pub mod udp;
+pub mod version;
 
-pub const SDCP_PACKET_HEADER: &str = "SDCP 0.0.1";
-
-const SDCP_COMMAND_SETP: &str = "SETP";
-const SDCP_COMMAND_GETP: &str = "GETP";
-const SDCP_COMMAND_BEAT: &str = "BEAT";
-const SDCP_COMMAND_INFO: &str = "INFO";
-const SDCP_COMMAND_NONE: &str = "NONE";
-
-#[allow(non_camel_case_types)]
-pub struct SDCP_COMMANDS;
+pub use crate::types::{
+    ParamValue, PayloadEncoding, SdcpCommand, SdcpErrorCode, SdcpResult, ValueCase,
+};
+pub use crate::version::{SdcpVersion, SDCP_BASE_VERSION, SDCP_VERSION};
 
-impl SDCP_COMMANDS {
-    pub const SETP: &'static str = SDCP_COMMAND_SETP;
-    pub const GETP: &'static str = SDCP_COMMAND_GETP;
-    pub const BEAT: &'static str = SDCP_COMMAND_BEAT;
-    pub const INFO: &'static str = SDCP_COMMAND_INFO;
-    pub const NONE: &'static str = SDCP_COMMAND_NONE;
-}
+pub const SDCP_PACKET_HEADER: &str = "SDCP 0.0.1";
 
 pub const SDCP_PARAM_STATUS: &str = "STATUS";
 pub const SDCP_PARAM_PWRCON: &str = "PWRCON";
+pub const SDCP_PARAM_VERSION: &str = "VERSION";
+pub const SDCP_PARAM_ENCODING: &str = "ENCODING";
+pub const SDCP_PARAM_DEVICE: &str = "DEVICE";
+pub const SDCP_PARAM_TYPE: &str = "TYPE";
+pub const SDCP_PARAM_ADDRESS: &str = "ADDRESS";
+pub const SDCP_PARAM_CAPABILITIES: &str = "CAPS";
+pub const SDCP_PARAM_ERROR: &str = "ERROR";
+pub const SDCP_PARAM_MESSAGE: &str = "MESSAGE";
 
-pub const SDCP_OK: &str = "OK";
-pub const SDCP_FAILED: &str = "FAILED";
+// Keys of the frame itself, compared before unescaping; parameters named like them
+// are written with an escaped first character
+const SDCP_KEY_ID: &str = "Id";
+const SDCP_KEY_COMMAND: &str = "Command";
+const SDCP_KEY_RESULT: &str = "Result";
 
+#[derive(Debug, Clone)]
 pub struct ParamItem {
     pub name: String,
-    pub value: String,
+    pub value: ParamValue,
 }
 
 impl ParamItem {
-    pub fn new(name: String, value: String) -> Self {
+    pub fn new(name: String, value: ParamValue) -> Self {
         Self { name, value }
     }
 }
 
-pub type SdcpRequestHandler = fn(FrameResult) -> SdcpFrame;
+#[async_trait]
+pub trait SdcpRequestHandler: Send + Sync {
+    async fn handle(&self, frame: FrameResult) -> SdcpFrame;
+}
+
+#[async_trait]
+impl<F, R> SdcpRequestHandler for F
+where
+    F: Fn(FrameResult) -> R + Send + Sync,
+    R: Future<Output = SdcpFrame> + Send,
+{
+    async fn handle(&self, frame: FrameResult) -> SdcpFrame {
+        self(frame).await
+    }
+}
 
+#[derive(Debug, Clone)]
 pub struct SdcpFrame {
     pub protocol: String,
-    pub command: String,
+    pub id: Option<u32>,
+    pub command: SdcpCommand,
     pub parameters: Vec<ParamItem>,
-    pub result: String,
+    pub result: SdcpResult,
 }
 
 pub struct NetConfig {
@@ -65,90 +114,364 @@ impl NetConfig {
     }
 }
 
+pub const SDCP_MAX_CONNECTIONS: usize = 64;
+pub const SDCP_SERVER_ERRORS_CAPACITY: usize = 64;
+
 pub struct SdcpHandler {
     address: SocketAddr,
+    options: SessionOptions,
+    max_connections: usize,
+    retry_policy: RetryPolicy,
+    request_retry_policy: RetryPolicy,
+    retryable_commands: Vec<SdcpCommand>,
+    #[cfg(feature = "tls")]
+    tls_server: Option<TlsServer>,
+    #[cfg(feature = "tls")]
+    tls_client: Option<TlsClient>,
+    notifier: Option<SdcpNotifier>,
+    batch_concurrency: usize,
+    batch_deadline: Option<Duration>,
+}
+
+pub struct SdcpServerHandle {
+    address: SocketAddr,
+    shutdown: CancellationToken,
+    task: JoinHandle<()>,
+    errors: Option<mpsc::Receiver<ServerError>>,
+}
+
+impl SdcpServerHandle {
+    pub fn local_address(&self) -> SocketAddr {
+        self.address
+    }
+    pub fn take_errors(&mut self) -> Option<mpsc::Receiver<ServerError>> {
+        self.errors.take()
+    }
+    pub async fn shutdown(self) {
+        self.shutdown.cancel();
+        let _ = self.task.await;
+    }
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+fn report(errors: &mpsc::Sender<ServerError>, error: ServerError) {
+    // Nobody may be listening for errors; a full or closed channel must not stall the server
+    let _ = errors.try_send(error);
 }
 
 impl SdcpHandler {
     pub fn new(address: SocketAddr) -> Self {
-        Self { address }
-    }
-    pub async fn bind(&self, handler: SdcpRequestHandler) {
-        let address = self.address;
-        tokio::spawn(async move {
-            let listener = match TcpListener::bind(address).await {
-                Ok(listener) => listener,
-                Err(error) => panic!("Binding error: {}", error),
-            };
+        Self {
+            address,
+            options: SessionOptions::default(),
+            max_connections: SDCP_MAX_CONNECTIONS,
+            retry_policy: RetryPolicy::default(),
+            request_retry_policy: RetryPolicy::default(),
+            retryable_commands: vec![SdcpCommand::Getp, SdcpCommand::Info, SdcpCommand::Beat],
+            #[cfg(feature = "tls")]
+            tls_server: None,
+            #[cfg(feature = "tls")]
+            tls_client: None,
+            notifier: None,
+            batch_concurrency: SDCP_BATCH_CONCURRENCY,
+            batch_deadline: Some(SDCP_BATCH_ITEM_DEADLINE),
+        }
+    }
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.options.codec = SdcpCodec::new(max_frame_size);
+    }
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections.max(1);
+    }
+    pub fn set_timeouts(&mut self, request: Option<Duration>, write: Option<Duration>) {
+        self.options.timeouts.request = request;
+        self.options.timeouts.write = write;
+    }
+    pub fn set_idle_timeout(&mut self, idle: Option<Duration>) {
+        self.options.timeouts.idle = idle;
+    }
+    pub fn set_connect_timeout(&mut self, connect: Option<Duration>) {
+        self.options.timeouts.connect = connect;
+    }
+    pub fn set_value_case(&mut self, value_case: ValueCase) {
+        self.options.value_case = value_case;
+    }
+    pub fn set_encoding(&mut self, encoding: PayloadEncoding) {
+        self.options.encoding = encoding;
+    }
+    pub fn set_version(&mut self, version: SdcpVersion) {
+        self.options.version = version;
+    }
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+    pub fn set_request_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.request_retry_policy = retry_policy;
+    }
+    pub fn set_batch_concurrency(&mut self, batch_concurrency: usize) {
+        self.batch_concurrency = batch_concurrency.max(1);
+    }
+    pub fn set_batch_deadline(&mut self, batch_deadline: Option<Duration>) {
+        self.batch_deadline = batch_deadline;
+    }
+    // Only commands without side effects should be listed here, a retried SETP may be applied twice
+    pub fn set_retryable_commands(&mut self, commands: Vec<SdcpCommand>) {
+        self.retryable_commands = commands;
+    }
+    #[cfg(feature = "tls")]
+    pub fn set_tls_server(&mut self, tls: TlsServer) {
+        self.tls_server = Some(tls);
+    }
+    #[cfg(feature = "tls")]
+    pub fn set_tls_client(&mut self, tls: TlsClient) {
+        self.tls_client = Some(tls);
+    }
+    // Without a notifier the server refuses SUBS, there is nothing to notify about
+    pub fn set_notifier(&mut self, notifier: SdcpNotifier) {
+        self.notifier = Some(notifier);
+    }
+    pub async fn bind<H: SdcpRequestHandler + 'static>(
+        &self,
+        handler: H,
+    ) -> ConnectResult<SdcpServerHandle> {
+        let listener = retry(self.retry_policy, is_transient, || {
+            TcpListener::bind(self.address)
+        })
+        .await?;
+        let local_address = listener.local_addr()?;
+        let handler: Arc<dyn SdcpRequestHandler> = Arc::new(handler);
+        let options = self.options;
+        let max_connections = self.max_connections;
+        #[cfg(feature = "tls")]
+        let tls = self.tls_server.clone();
+        let notifier = self.notifier.clone();
+        let shutdown = CancellationToken::new();
+        let server_shutdown = shutdown.clone();
+        let (errors, errors_receiver) = mpsc::channel(SDCP_SERVER_ERRORS_CAPACITY);
+        let task = tokio::spawn(async move {
+            let connections = Arc::new(Semaphore::new(max_connections));
             loop {
-                println!("In loop");
-                match listener.accept().await {
-                    Ok((mut stream, address)) => {
-                        println!("Source address: {}", address);
-                        match recv_packet(&stream).await {
-                            Ok(packet) => {
-                                match send_packet(
-                                    make_packet(handler(make_frame(packet))),
-                                    &mut stream,
-                                )
-                                .await
-                                {
-                                    Ok(()) => (),
-                                    Err(error) => println!("Packet sending error: {}", error),
-                                }
-                            }
-                            Err(error) => println!("Error receiving package: {}", error),
+                let permit = tokio::select! {
+                    _ = server_shutdown.cancelled() => break,
+                    permit = Arc::clone(&connections).acquire_owned() => match permit {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    },
+                };
+                let (stream, address) = tokio::select! {
+                    _ = server_shutdown.cancelled() => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(error) => {
+                            report(&errors, ServerError::Accept(error));
+                            continue;
                         }
-                    }
-                    Err(error) => println!("Error receiving package: {}", error),
+                    },
+                };
+                if let Err(error) = stream.set_nodelay(true) {
+                    report(&errors, ServerError::Socket(address, error));
                 }
+                let connection = Connection {
+                    stream,
+                    address,
+                    options,
+                    #[cfg(feature = "tls")]
+                    tls: tls.clone(),
+                    notifier: notifier.clone(),
+                    handler: Arc::clone(&handler),
+                    shutdown: server_shutdown.clone(),
+                };
+                let errors = errors.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = serve_connection(connection).await {
+                        report(&errors, error);
+                    }
+                    drop(permit);
+                });
             }
+            drop(listener);
+            // Every active session holds a permit, so this waits for all of them to finish
+            let _ = connections.acquire_many(max_connections as u32).await;
         });
+        Ok(SdcpServerHandle {
+            address: local_address,
+            shutdown,
+            task,
+            errors: Some(errors_receiver),
+        })
     }
 
     pub async fn request(&self, frame: SdcpFrame, address: SocketAddr) -> RequestResult {
-        match TcpStream::connect(address).await {
-            Ok(mut stream) => match send_packet(make_packet(frame), &mut stream).await {
-                Ok(_) => match recv_packet(&stream).await {
-                    Ok(packet) => match make_frame(packet) {
-                        Ok(frame) => Ok(frame),
-                        Err(_) => Err(RequestError::InvalidPacket),
-                    },
-                    Err(error) => Err(RequestError::Recv(error)),
-                },
-                Err(error) => Err(RequestError::Send(error)),
-            },
-            Err(error) => Err(RequestError::Io(error)),
+        let mut retry_policy = self.request_retry_policy;
+        if !self.retryable_commands.contains(&frame.command) {
+            retry_policy.retries = 0;
+        }
+        retry(retry_policy, RequestError::is_transient, || {
+            self.request_once(&frame, address)
+        })
+        .await
+    }
+
+    // Runs at most batch_concurrency requests at a time, every device gets its own result;
+    // an item still unanswered at the batch deadline fails with Timeout
+    pub async fn request_batch(&self, requests: Vec<(SocketAddr, SdcpFrame)>) -> BatchReport {
+        let items = stream::iter(requests)
+            .map(|(address, frame)| async move {
+                BatchItem {
+                    address,
+                    result: with_timeout(self.batch_deadline, self.request(frame, address))
+                        .await
+                        .unwrap_or(Err(RequestError::Timeout)),
+                }
+            })
+            .buffered(self.batch_concurrency)
+            .collect()
+            .await;
+        BatchReport::new(items)
+    }
+
+    async fn request_once(&self, frame: &SdcpFrame, address: SocketAddr) -> RequestResult {
+        let timeouts = self.options.timeouts;
+        let stream = with_timeout(timeouts.connect, TcpStream::connect(address))
+            .await
+            .map_err(|_| RequestError::Timeout)??;
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls_client {
+            let stream = with_timeout(timeouts.connect, tls.connect(stream))
+                .await
+                .map_err(|_| RequestError::Timeout)??;
+            return self.exchange(stream, frame).await;
         }
+        self.exchange(stream, frame).await
+    }
+
+    async fn exchange<S>(&self, stream: S, frame: &SdcpFrame) -> RequestResult
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let timeouts = self.options.timeouts;
+        let mut stream = Framed::new(stream, self.options.handshake_codec());
+        // Text needs no handshake, any other encoding has to be agreed on first
+        if self.options.encoding != PayloadEncoding::Text {
+            handshake(&mut stream, &self.options).await?;
+        }
+        with_timeout(timeouts.write, send_packet(frame.clone(), &mut stream))
+            .await
+            .map_err(|_| RequestError::Timeout)??;
+        let frame = with_timeout(timeouts.request, recv_packet(&mut stream))
+            .await
+            .map_err(|_| RequestError::Timeout)??;
+        frame
+            .map_err(|_| RequestError::InvalidPacket)
+            .and_then(check_response)
+    }
+}
+
+// Everything a spawned connection task needs from the server
+struct Connection {
+    stream: TcpStream,
+    address: SocketAddr,
+    options: SessionOptions,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsServer>,
+    notifier: Option<SdcpNotifier>,
+    handler: Arc<dyn SdcpRequestHandler>,
+    shutdown: CancellationToken,
+}
+
+async fn serve_connection(connection: Connection) -> Result<(), ServerError> {
+    let Connection {
+        stream,
+        address,
+        options,
+        notifier,
+        handler,
+        shutdown,
+        ..
+    } = connection;
+    #[cfg(feature = "tls")]
+    if let Some(tls) = connection.tls {
+        let stream = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            stream = with_timeout(options.timeouts.connect, tls.accept(stream)) => stream,
+        };
+        let stream = stream
+            .map_err(|_| ServerError::Session(address, SessionError::Timeout))?
+            .map_err(|error| ServerError::Tls(address, error))?;
+        return serve_session(stream, options, notifier, handler, shutdown)
+            .await
+            .map_err(|error| ServerError::Session(address, error));
     }
+    serve_session(stream, options, notifier, handler, shutdown)
+        .await
+        .map_err(|error| ServerError::Session(address, error))
 }
 
 pub fn make_frame(data: String) -> FrameResult {
+    make_frame_with_case(data, ValueCase::Upper)
+}
+
+pub fn make_frame_with_case(data: String, value_case: ValueCase) -> FrameResult {
     let mut frame: SdcpFrame = SdcpFrame {
         protocol: SDCP_PACKET_HEADER.to_string(),
-        command: SDCP_COMMAND_NONE.to_string(),
+        id: None,
+        command: SdcpCommand::None,
         parameters: vec![],
-        result: SDCP_OK.to_string(),
+        result: SdcpResult::Ok,
     };
-    for item in data.split(';').collect::<Vec<&str>>().iter() {
+    for item in split_unescaped(&data, ';')? {
         if !item.is_empty() {
             // exclude tail
-            let pair = item.split('=').collect::<Vec<&str>>();
-            if pair.len() != 2 {
+            let pair = split_unescaped(item, '=')?;
+            if pair.len() < 2 {
                 return Err(FrameError::InvalidPacket);
-            } else if "Command".to_uppercase().eq(&pair[0].to_uppercase()) && !pair[1].is_empty() {
-                frame.command = pair[1].to_string().to_uppercase();
-            } else if "Result".to_uppercase().eq(&pair[0].to_uppercase()) {
-                frame.result = pair[1].to_string().to_uppercase();
+            }
+            // Unescaped '=' are allowed in values, so the value is everything after the first one
+            let key = pair[0];
+            let name = unescape(key)?;
+            let value = unescape(&item[key.len() + 1..])?;
+            if key.eq_ignore_ascii_case(SDCP_KEY_COMMAND) && !value.is_empty() {
+                frame.command = value.parse().unwrap();
+            } else if key.eq_ignore_ascii_case(SDCP_KEY_RESULT) {
+                frame.result = value.parse().unwrap();
+            } else if key.eq_ignore_ascii_case(SDCP_KEY_ID) {
+                frame.id = Some(value.parse().map_err(|_| FrameError::InvalidPacket)?);
             } else {
-                frame.parameters.push(ParamItem::new(
-                    pair[0].to_string().to_uppercase(),
-                    pair[1].to_string().to_uppercase(),
-                ));
+                let value = match DeviceError::keeps_case(&name) {
+                    true => ParamValue::from_text(&value),
+                    false => ParamValue::from_text(&value_case.apply(&value)),
+                };
+                frame
+                    .parameters
+                    .push(ParamItem::new(name.to_uppercase(), value));
             }
         }
     }
-    if !frame.parameters.is_empty() {
+    check_frame(frame)
+}
+
+// Finds the id of a packet that cannot be read as a whole
+pub(crate) fn text_packet_id(data: &str) -> Option<u32> {
+    data.split(';').find_map(|item| {
+        let (key, value) = item.split_once('=')?;
+        key.eq_ignore_ascii_case(SDCP_KEY_ID)
+            .then(|| value.parse().ok())
+            .flatten()
+    })
+}
+
+fn is_frame_key(name: &str) -> bool {
+    [SDCP_KEY_ID, SDCP_KEY_COMMAND, SDCP_KEY_RESULT]
+        .iter()
+        .any(|key| key.eq_ignore_ascii_case(name))
+}
+
+pub(crate) fn check_frame(frame: SdcpFrame) -> FrameResult {
+    let parameters_required = frame.result == SdcpResult::Ok
+        && matches!(frame.command, SdcpCommand::Getp | SdcpCommand::Setp);
+    if !parameters_required || !frame.parameters.is_empty() {
         Ok(frame)
     } else {
         Err(FrameError::InvalidPacket)
@@ -158,61 +481,46 @@ pub fn make_frame(data: String) -> FrameResult {
 pub fn make_packet(frame: SdcpFrame) -> String {
     let mut data: String = String::new();
 
-    write!(data, "Command={};", frame.command).unwrap();
-    write!(data, "Result={};", frame.result).unwrap();
+    if let Some(id) = frame.id {
+        write!(data, "{}={};", SDCP_KEY_ID, id).unwrap();
+    }
+    write!(
+        data,
+        "{}={};",
+        SDCP_KEY_COMMAND,
+        escape(&frame.command.to_string())
+    )
+    .unwrap();
+    write!(
+        data,
+        "{}={};",
+        SDCP_KEY_RESULT,
+        escape(&frame.result.to_string())
+    )
+    .unwrap();
     for parameter in frame.parameters.iter() {
-        write!(data, "{}={};", parameter.name, parameter.value).unwrap();
+        let name = match is_frame_key(&parameter.name) {
+            true => escape_key(&parameter.name),
+            false => escape(&parameter.name),
+        };
+        write!(data, "{}={};", name, escape(&parameter.value.to_text())).unwrap();
     }
 
     data
 }
 
-pub async fn send_packet<D: AsRef<str>>(data: D, stream: &mut TcpStream) -> SendResult {
-    let header_bytes = SDCP_PACKET_HEADER.as_bytes();
-
-    let data_bytes = data.as_ref().as_bytes();
-    let data_bytes_length = (data_bytes.len() as u32).to_be_bytes();
-
-    stream.write_all(header_bytes).await?;
-    stream.write_all(&data_bytes_length).await?;
-    stream.write_all(data_bytes).await?;
-    Ok(())
+pub async fn send_packet<D, S: Sink<D, Error = SendError> + Unpin>(
+    data: D,
+    stream: &mut S,
+) -> SendResult {
+    stream.send(data).await
 }
 
-async fn recv_packet(stream: &TcpStream) -> RecvResult {
-    let mut packet = [0; 4096];
-    stream.readable().await?;
-
-    match stream.try_read(&mut packet) {
-        Ok(0) => Err(RecvError::InvalidPacket),
-        Ok(n) => {
-            if n < SDCP_PACKET_HEADER.len() + 4 {
-                return Err(RecvError::InvalidPacket);
-            }
-            let protocol = str::from_utf8(&packet[0..SDCP_PACKET_HEADER.len()])
-                .map_err(|_| RecvError::BadEncoding);
-            match protocol {
-                Ok(protocol) => {
-                    if !protocol.eq(SDCP_PACKET_HEADER) {
-                        return Err(RecvError::InvalidPacket);
-                    }
-                }
-                Err(error) => return Err(error),
-            }
-            let data_length = u32::from_be_bytes([
-                packet[SDCP_PACKET_HEADER.len()],
-                packet[SDCP_PACKET_HEADER.len() + 1],
-                packet[SDCP_PACKET_HEADER.len() + 2],
-                packet[SDCP_PACKET_HEADER.len() + 3],
-            ]);
-            if n < SDCP_PACKET_HEADER.len() + 4 + (data_length as usize) {
-                return Err(RecvError::InvalidPacket);
-            }
-            let data = packet[SDCP_PACKET_HEADER.len() + 4
-                ..SDCP_PACKET_HEADER.len() + 4 + (data_length as usize)]
-                .to_vec();
-            String::from_utf8(data).map_err(|_| RecvError::BadEncoding)
-        }
-        Err(_) => Err(RecvError::InvalidPacket),
+pub async fn recv_packet<T, S: Stream<Item = Result<T, RecvError>> + Unpin>(
+    stream: &mut S,
+) -> Result<T, RecvError> {
+    match stream.next().await {
+        Some(packet) => packet,
+        None => Err(RecvError::Closed),
     }
 }
@@ -1,6 +1,11 @@
-use crate::{ParamItem, SdcpFrame};
+use crate::{ParamItem, SdcpErrorCode, SdcpFrame, SdcpVersion};
 use std::io;
+use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
 use thiserror::Error;
+#[cfg(feature = "tls")]
+use tokio_rustls::rustls;
 
 pub type RequestResult = Result<SdcpFrame, RequestError>;
 
@@ -16,6 +21,45 @@ pub enum RequestError {
     InvalidPacket,
     #[error("Invalid frame")]
     InvalidFrame(#[from] FrameError),
+    #[error("Session closed")]
+    SessionClosed,
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Device error {code}: {message}")]
+    Device {
+        code: SdcpErrorCode,
+        message: String,
+    },
+}
+
+impl RequestError {
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout
+                | Self::Device {
+                    code: SdcpErrorCode::Busy,
+                    ..
+                }
+                | Self::Io(_)
+                | Self::Send(SendError::Io(_))
+                | Self::Recv(RecvError::Io(_) | RecvError::Closed)
+        )
+    }
+}
+
+// A one-shot request that negotiates first fails with the handshake's own error
+impl From<ConnError> for RequestError {
+    fn from(error: ConnError) -> Self {
+        match error {
+            ConnError::Io(error) => Self::Io(error),
+            ConnError::Timeout => Self::Timeout,
+            ConnError::Handshake(error) => error,
+            ConnError::UnsupportedVersion(version) => {
+                Self::Recv(RecvError::UnsupportedVersion(version))
+            }
+        }
+    }
 }
 
 pub type NetResult = Result<Vec<ParamItem>, NetError>;
@@ -28,6 +72,16 @@ pub enum NetError {
     Send(#[from] SendError),
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    #[error(transparent)]
+    Request(#[from] RequestError),
+    #[error(transparent)]
+    Frame(#[from] FrameError),
+    #[error("Device did not report in time")]
+    Timeout,
+    #[error("Unsupported parameter {0}")]
+    UnsupportedParameter(String),
+    #[error("Device is not attached to a telemetry receiver")]
+    NoTelemetry,
 }
 
 pub type ConnectResult<T> = Result<T, ConnError>;
@@ -36,6 +90,25 @@ pub type ConnectResult<T> = Result<T, ConnError>;
 pub enum ConnError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    #[error("Connection timed out")]
+    Timeout,
+    #[error("Handshake failed: {0}")]
+    Handshake(#[from] RequestError),
+    #[error("Unsupported protocol version {0}")]
+    UnsupportedVersion(SdcpVersion),
+}
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("Accept error: {0}")]
+    Accept(io::Error),
+    #[error("Socket configuration error for {0}: {1}")]
+    Socket(SocketAddr, io::Error),
+    #[error("Session error for {0}: {1}")]
+    Session(SocketAddr, SessionError),
+    #[cfg(feature = "tls")]
+    #[error("TLS handshake error for {0}: {1}")]
+    Tls(SocketAddr, io::Error),
 }
 
 pub type SendResult = Result<(), SendError>;
@@ -44,6 +117,8 @@ pub type SendResult = Result<(), SendError>;
 pub enum SendError {
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    #[error("frame of {0} bytes exceeds the maximum frame size")]
+    FrameTooLarge(usize),
 }
 
 pub type RecvResult = Result<String, RecvError>;
@@ -56,6 +131,24 @@ pub enum RecvError {
     BadEncoding,
     #[error("invalid packet")]
     InvalidPacket,
+    #[error("connection closed")]
+    Closed,
+    #[error("frame of {0} bytes exceeds the maximum frame size")]
+    FrameTooLarge(usize),
+    #[error("unsupported protocol version {0}")]
+    UnsupportedVersion(SdcpVersion),
+}
+
+pub type SessionResult = Result<(), SessionError>;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error(transparent)]
+    Recv(#[from] RecvError),
+    #[error(transparent)]
+    Send(#[from] SendError),
+    #[error("session timed out")]
+    Timeout,
 }
 
 pub type FrameResult = Result<SdcpFrame, FrameError>;
@@ -64,8 +157,80 @@ pub type FrameResult = Result<SdcpFrame, FrameError>;
 pub enum FrameError {
     #[error(transparent)]
     Recv(#[from] RecvError),
+    #[error(transparent)]
+    Escape(#[from] EscapeError),
     #[error("invalid packet")]
     InvalidPacket,
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    // The id of a request survives when the rest of it cannot be read, so the reply can echo it
+    #[error("invalid request {id}: {error}")]
+    InvalidRequest { id: u32, error: Box<FrameError> },
+}
+
+impl FrameError {
+    pub fn request_id(&self) -> Option<u32> {
+        match self {
+            Self::InvalidRequest { id, .. } => Some(*id),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn with_request_id(self, id: Option<u32>) -> Self {
+        match (id, self) {
+            (Some(id), error @ (Self::Recv(_) | Self::Escape(_) | Self::InvalidPacket)) => {
+                Self::InvalidRequest {
+                    id,
+                    error: Box::new(error),
+                }
+            }
+            (_, error) => error,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("datagram is not signed")]
+    Unsigned,
+    #[error("no key for device {0}")]
+    UnknownDevice(String),
+    #[error("bad datagram signature")]
+    BadSignature,
+    #[error("replayed datagram")]
+    Replayed,
+    #[error("datagram timestamp is out of range")]
+    Stale,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid escape sequence")]
+pub struct EscapeError;
+
+#[derive(Debug, Error)]
+#[error("invalid version")]
+pub struct VersionError;
+
+#[derive(Debug, Error)]
+#[error("unknown payload encoding")]
+pub struct EncodingError;
+
+#[cfg(feature = "tls")]
+pub type TlsResult<T> = Result<T, TlsError>;
+
+#[cfg(feature = "tls")]
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("PEM error: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+    #[error("no certificates found in {0}")]
+    NoCertificates(PathBuf),
+    #[error("TLS error: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("client verifier error: {0}")]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+    #[error("invalid server name {0}")]
+    InvalidServerName(String),
 }
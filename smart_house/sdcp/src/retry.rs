@@ -0,0 +1,65 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32, backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            retries,
+            backoff,
+            max_backoff,
+        }
+    }
+
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(0, Duration::from_millis(100), Duration::from_secs(5))
+    }
+}
+
+pub fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::AddrInUse
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+    )
+}
+
+pub async fn retry<T, E, F, R, P>(
+    policy: RetryPolicy,
+    retryable: P,
+    mut operation: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<T, E>>,
+    P: Fn(&E) -> bool,
+{
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Err(error) if attempt < policy.retries && retryable(&error) => {
+                tokio::time::sleep(policy.delay(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
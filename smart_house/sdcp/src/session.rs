@@ -0,0 +1,536 @@
+use futures::stream::BoxStream;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, oneshot, Mutex as AsyncMutex};
+use tokio::task::JoinHandle;
+use tokio::time::error::Elapsed;
+use tokio::time::Instant;
+use tokio_util::codec::{Framed, FramedRead, FramedWrite};
+use tokio_util::sync::CancellationToken;
+
+use crate::codec::{SdcpCodec, SdcpFrameCodec};
+use crate::device_error::{check_response, DeviceError};
+use crate::events::{
+    event_stream, next_event, notify_frame, SdcpNotifier, Subscriptions, SDCP_EVENTS_CAPACITY,
+};
+use crate::results::{
+    ConnError, ConnectResult, RecvError, RequestError, RequestResult, SessionError, SessionResult,
+};
+#[cfg(feature = "tls")]
+use crate::tls::TlsClient;
+use crate::{
+    recv_packet, send_packet, ParamItem, ParamValue, PayloadEncoding, SdcpCommand, SdcpErrorCode,
+    SdcpFrame, SdcpRequestHandler, SdcpResult, SdcpVersion, ValueCase, SDCP_BASE_VERSION,
+    SDCP_PACKET_HEADER, SDCP_PARAM_ENCODING, SDCP_PARAM_VERSION,
+};
+
+type SessionWriter = FramedWrite<Pin<Box<dyn AsyncWrite + Send>>, SdcpFrameCodec>;
+
+struct SessionState {
+    writer: AsyncMutex<SessionWriter>,
+    pending: Mutex<HashMap<u32, oneshot::Sender<RequestResult>>>,
+    next_id: AtomicU32,
+    closed: AtomicBool,
+    timeouts: SessionTimeouts,
+    events: Mutex<Option<broadcast::Sender<ParamItem>>>,
+}
+
+impl SessionState {
+    async fn request(&self, mut frame: SdcpFrame) -> RequestResult {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if self.closed.load(Ordering::SeqCst) {
+                return Err(RequestError::SessionClosed);
+            }
+            pending.insert(id, sender);
+        }
+        frame.id = Some(id);
+        let result = self.send_and_wait(frame, receiver).await;
+        if result.is_err() {
+            self.pending.lock().unwrap().remove(&id);
+        }
+        result.and_then(check_response)
+    }
+
+    async fn send_and_wait(
+        &self,
+        frame: SdcpFrame,
+        receiver: oneshot::Receiver<RequestResult>,
+    ) -> RequestResult {
+        let sent = with_timeout(self.timeouts.write, async {
+            let mut writer = self.writer.lock().await;
+            send_packet(frame, &mut *writer).await
+        })
+        .await;
+        sent.map_err(|_| RequestError::Timeout)??;
+        with_timeout(self.timeouts.request, receiver)
+            .await
+            .map_err(|_| RequestError::Timeout)?
+            .map_err(|_| RequestError::SessionClosed)?
+    }
+
+    fn complete(&self, id: u32, result: RequestResult) {
+        if let Some(sender) = self.pending.lock().unwrap().remove(&id) {
+            let _ = sender.send(result);
+        }
+    }
+
+    // Used when a reply cannot be matched to its request, every waiting request gets the error
+    fn fail_pending(&self, error: impl Fn() -> RequestError) {
+        for (_, sender) in self.pending.lock().unwrap().drain() {
+            let _ = sender.send(Err(error()));
+        }
+    }
+
+    fn close(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        // Dropping the senders wakes every waiting request with SessionClosed
+        pending.clear();
+        // and ends every notification stream
+        self.events.lock().unwrap().take();
+    }
+}
+
+pub struct SdcpSession {
+    state: Arc<SessionState>,
+    version: SdcpVersion,
+    encoding: PayloadEncoding,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SdcpSession {
+    pub async fn connect(address: SocketAddr, options: SessionOptions) -> ConnectResult<Self> {
+        let stream = tcp_connect(address, &options).await?;
+        Self::open(stream, options).await
+    }
+
+    #[cfg(feature = "tls")]
+    pub async fn connect_tls(
+        address: SocketAddr,
+        options: SessionOptions,
+        tls: &TlsClient,
+    ) -> ConnectResult<Self> {
+        let stream = tcp_connect(address, &options).await?;
+        let stream = with_timeout(options.timeouts.connect, tls.connect(stream))
+            .await
+            .map_err(|_| ConnError::Timeout)??;
+        Self::open(stream, options).await
+    }
+
+    async fn open<S>(stream: S, options: SessionOptions) -> ConnectResult<Self>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut stream = Framed::new(stream, options.handshake_codec());
+        let (version, encoding) = handshake(&mut stream, &options).await?;
+        let mut options = options;
+        options.codec.set_version(version);
+        options.encoding = encoding;
+        Ok(Self::from_stream(stream.into_inner(), options))
+    }
+
+    pub fn from_stream<S>(stream: S, options: SessionOptions) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let writer: Pin<Box<dyn AsyncWrite + Send>> = Box::pin(writer);
+        let state = Arc::new(SessionState {
+            writer: AsyncMutex::new(FramedWrite::new(writer, options.frame_codec())),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU32::new(1),
+            closed: AtomicBool::new(false),
+            timeouts: options.timeouts,
+            events: Mutex::new(Some(broadcast::channel(SDCP_EVENTS_CAPACITY).0)),
+        });
+        let reader = FramedRead::new(reader, options.frame_codec());
+        let mut tasks = vec![tokio::spawn(read_responses(reader, Arc::clone(&state)))];
+        if let Some(interval) = options.keepalive {
+            tasks.push(tokio::spawn(keep_alive(Arc::clone(&state), interval)));
+        }
+        Self {
+            state,
+            version: options.codec.version(),
+            encoding: options.encoding,
+            tasks,
+        }
+    }
+
+    pub async fn request(&self, frame: SdcpFrame) -> RequestResult {
+        self.state.request(frame).await
+    }
+
+    // A server without events refuses the subscription with UNSUPPORTED_COMMAND
+    pub async fn subscribe(&self, names: &[&str]) -> RequestResult {
+        self.request(subscription_frame(SdcpCommand::Subs, names))
+            .await
+    }
+
+    pub async fn unsubscribe(&self, names: &[&str]) -> RequestResult {
+        self.request(subscription_frame(SdcpCommand::Unsu, names))
+            .await
+    }
+
+    pub fn notifications(&self) -> BoxStream<'static, ParamItem> {
+        event_stream(
+            self.state
+                .events
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(broadcast::Sender::subscribe),
+        )
+    }
+
+    pub fn version(&self) -> SdcpVersion {
+        self.version
+    }
+
+    pub fn encoding(&self) -> PayloadEncoding {
+        self.encoding
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.closed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for SdcpSession {
+    fn drop(&mut self) {
+        for task in self.tasks.iter() {
+            task.abort();
+        }
+    }
+}
+
+fn subscription_frame(command: SdcpCommand, names: &[&str]) -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command,
+        parameters: names
+            .iter()
+            .map(|name| ParamItem::new(name.to_uppercase(), ParamValue::from("")))
+            .collect(),
+        result: SdcpResult::Ok,
+    }
+}
+
+async fn tcp_connect(address: SocketAddr, options: &SessionOptions) -> ConnectResult<TcpStream> {
+    let stream = with_timeout(options.timeouts.connect, TcpStream::connect(address))
+        .await
+        .map_err(|_| ConnError::Timeout)??;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+async fn read_responses<S: AsyncRead>(
+    mut reader: FramedRead<ReadHalf<S>, SdcpFrameCodec>,
+    state: Arc<SessionState>,
+) {
+    while let Ok(frame) = recv_packet(&mut reader).await {
+        match frame {
+            Ok(frame) if frame.id.is_none() && frame.command == SdcpCommand::Noti => {
+                if let Some(events) = state.events.lock().unwrap().as_ref() {
+                    for item in frame.parameters {
+                        let _ = events.send(item);
+                    }
+                }
+            }
+            Ok(frame) => match (frame.id, DeviceError::from_frame(&frame)) {
+                (Some(id), _) => state.complete(id, Ok(frame)),
+                // An error without an id is about a request the peer could not read
+                (None, Some(error)) => state.fail_pending(|| error.clone().into()),
+                (None, None) => (),
+            },
+            Err(error) => match error.request_id() {
+                Some(id) => state.complete(id, Err(RequestError::InvalidFrame(error))),
+                None => state.fail_pending(|| RequestError::InvalidPacket),
+            },
+        }
+    }
+    state.close();
+}
+
+async fn keep_alive(state: Arc<SessionState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let beat = SdcpFrame {
+            protocol: SDCP_PACKET_HEADER.to_string(),
+            id: None,
+            command: SdcpCommand::Beat,
+            parameters: vec![],
+            result: SdcpResult::Ok,
+        };
+        match tokio::time::timeout(interval, state.request(beat)).await {
+            Ok(Ok(_)) => (),
+            _ => {
+                state.close();
+                return;
+            }
+        }
+    }
+}
+
+pub const SDCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const SDCP_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+pub const SDCP_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+pub const SDCP_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The server drops a peer silent for longer than idle, the client gives up on an
+// answer after request; None waits forever
+#[derive(Debug, Clone, Copy)]
+pub struct SessionTimeouts {
+    pub connect: Option<Duration>,
+    pub idle: Option<Duration>,
+    pub request: Option<Duration>,
+    pub write: Option<Duration>,
+}
+
+impl Default for SessionTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(SDCP_CONNECT_TIMEOUT),
+            idle: Some(SDCP_IDLE_TIMEOUT),
+            request: Some(SDCP_REQUEST_TIMEOUT),
+            write: Some(SDCP_WRITE_TIMEOUT),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionOptions {
+    pub codec: SdcpCodec,
+    pub value_case: ValueCase,
+    pub timeouts: SessionTimeouts,
+    pub keepalive: Option<Duration>,
+    pub version: SdcpVersion,
+    pub encoding: PayloadEncoding,
+}
+
+impl SessionOptions {
+    fn frame_codec(&self) -> SdcpFrameCodec {
+        SdcpFrameCodec::new(self.codec, self.encoding, self.value_case)
+    }
+
+    // Every connection starts in text, the handshake is what switches it to another encoding
+    pub(crate) fn handshake_codec(&self) -> SdcpFrameCodec {
+        SdcpFrameCodec::new(self.codec, PayloadEncoding::Text, self.value_case)
+    }
+}
+
+pub(crate) async fn with_timeout<F: Future>(
+    duration: Option<Duration>,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    match duration {
+        Some(duration) => tokio::time::timeout(duration, future).await,
+        None => Ok(future.await),
+    }
+}
+
+fn idle_deadline(idle: Option<Duration>) -> Option<Instant> {
+    idle.map(|idle| Instant::now() + idle)
+}
+
+async fn with_deadline<F: Future>(
+    deadline: Option<Instant>,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await,
+        None => Ok(future.await),
+    }
+}
+
+pub(crate) async fn serve_session<S>(
+    stream: S,
+    options: SessionOptions,
+    notifier: Option<SdcpNotifier>,
+    handler: Arc<dyn SdcpRequestHandler>,
+    shutdown: CancellationToken,
+) -> SessionResult
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = Framed::new(stream, options.handshake_codec());
+    let mut events = notifier.as_ref().map(SdcpNotifier::subscribe);
+    let mut subscriptions = Subscriptions::default();
+    // Notifications going out must not extend the idle timeout, only incoming frames do
+    let mut deadline = idle_deadline(options.timeouts.idle);
+    loop {
+        let frame = tokio::select! {
+            _ = shutdown.cancelled() => return Ok(()),
+            item = next_event(&mut events) => {
+                if subscriptions.contains(&item.name) {
+                    with_timeout(options.timeouts.write, send_packet(notify_frame(item), &mut stream))
+                        .await
+                        .map_err(|_| SessionError::Timeout)??;
+                }
+                continue;
+            }
+            frame = with_deadline(deadline, recv_packet(&mut stream)) => frame,
+        };
+        deadline = idle_deadline(options.timeouts.idle);
+        let frame = match frame {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(RecvError::Closed)) => return Ok(()),
+            Ok(Err(error)) => return Err(error.into()),
+            Err(_) => return Err(SessionError::Timeout),
+        };
+        let peer_version = frame
+            .as_ref()
+            .ok()
+            .filter(|frame| frame.command == SdcpCommand::Info)
+            .and_then(version_param);
+        let mut negotiated = None;
+        let response = match (frame, peer_version) {
+            (Ok(frame), _) if frame.command == SdcpCommand::Beat => SdcpFrame {
+                protocol: stream.codec().version().header(),
+                id: frame.id,
+                command: SdcpCommand::Beat,
+                parameters: vec![],
+                result: SdcpResult::Ok,
+            },
+            (Ok(frame), _) if matches!(frame.command, SdcpCommand::Subs | SdcpCommand::Unsu) => {
+                match (&notifier, &frame.command) {
+                    (None, _) => SdcpFrame {
+                        id: frame.id,
+                        ..DeviceError::new(
+                            SdcpErrorCode::UnsupportedCommand,
+                            "notifications are not supported",
+                        )
+                        .frame(frame.command)
+                    },
+                    (Some(_), SdcpCommand::Subs) => {
+                        subscriptions.subscribe(&frame);
+                        frame
+                    }
+                    (Some(_), _) => {
+                        subscriptions.unsubscribe(&frame);
+                        frame
+                    }
+                }
+            }
+            (Ok(frame), Some(peer_version)) => match options.version.negotiate(&peer_version) {
+                Some(version) => {
+                    let encoding = options
+                        .encoding
+                        .negotiate(&encoding_param(&frame).unwrap_or_default());
+                    negotiated = Some((version, encoding));
+                    version_frame(frame.id, version, encoding, SdcpResult::Ok)
+                }
+                None => version_frame(
+                    frame.id,
+                    options.version,
+                    PayloadEncoding::Text,
+                    SdcpResult::Failed,
+                ),
+            },
+            (frame, _) => {
+                let id = match &frame {
+                    Ok(frame) => frame.id,
+                    Err(error) => error.request_id(),
+                };
+                let mut response = handler.handle(frame).await;
+                response.id = id;
+                response
+            }
+        };
+        with_timeout(options.timeouts.write, send_packet(response, &mut stream))
+            .await
+            .map_err(|_| SessionError::Timeout)??;
+        // The handshake response still goes out in the old format, the peer switches after it
+        if let Some((version, encoding)) = negotiated {
+            stream.codec_mut().set_version(version);
+            stream.codec_mut().set_encoding(encoding);
+        }
+    }
+}
+
+fn version_frame(
+    id: Option<u32>,
+    version: SdcpVersion,
+    encoding: PayloadEncoding,
+    result: SdcpResult,
+) -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id,
+        command: SdcpCommand::Info,
+        parameters: vec![
+            ParamItem::new(
+                SDCP_PARAM_VERSION.to_string(),
+                ParamValue::from(version.to_string()),
+            ),
+            ParamItem::new(
+                SDCP_PARAM_ENCODING.to_string(),
+                ParamValue::from(encoding.to_string()),
+            ),
+        ],
+        result,
+    }
+}
+
+fn find_param<T: FromStr>(frame: &SdcpFrame, name: &str) -> Option<T> {
+    frame
+        .parameters
+        .iter()
+        .find(|item| item.name.eq(name))
+        .and_then(|item| item.value.to_string().parse().ok())
+}
+
+fn version_param(frame: &SdcpFrame) -> Option<SdcpVersion> {
+    find_param(frame, SDCP_PARAM_VERSION)
+}
+
+fn encoding_param(frame: &SdcpFrame) -> Option<PayloadEncoding> {
+    find_param(frame, SDCP_PARAM_ENCODING)
+}
+
+// Switches the stream to the negotiated version and encoding once the peer agrees
+pub(crate) async fn handshake<S>(
+    stream: &mut Framed<S, SdcpFrameCodec>,
+    options: &SessionOptions,
+) -> ConnectResult<(SdcpVersion, PayloadEncoding)>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let frame = version_frame(None, options.version, options.encoding, SdcpResult::Ok);
+    with_timeout(options.timeouts.write, send_packet(frame, &mut *stream))
+        .await
+        .map_err(|_| RequestError::Timeout)?
+        .map_err(RequestError::Send)?;
+    let response = with_timeout(options.timeouts.request, recv_packet(&mut *stream))
+        .await
+        .map_err(|_| RequestError::Timeout)?
+        .map_err(RequestError::Recv)?
+        .map_err(RequestError::from)?;
+    // Peers older than the negotiation know neither VERSION nor ENCODING
+    let encoding = encoding_param(&response).unwrap_or_default();
+    let (version, encoding) = match version_param(&response) {
+        Some(version) if response.result == SdcpResult::Ok => options
+            .version
+            .negotiate(&version)
+            .map(|version| (version, encoding))
+            .ok_or(ConnError::UnsupportedVersion(version))?,
+        Some(version) => return Err(ConnError::UnsupportedVersion(version)),
+        None => (SDCP_BASE_VERSION, PayloadEncoding::Text),
+    };
+    stream.codec_mut().set_version(version);
+    stream.codec_mut().set_encoding(encoding);
+    Ok((version, encoding))
+}
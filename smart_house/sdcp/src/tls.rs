@@ -0,0 +1,121 @@
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream as ClientTlsStream;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream as ServerTlsStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::results::{TlsError, TlsResult};
+
+pub fn load_certs(path: &Path) -> TlsResult<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)?.collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificates(path.to_path_buf()));
+    }
+    Ok(certs)
+}
+
+pub fn load_private_key(path: &Path) -> TlsResult<PrivateKeyDer<'static>> {
+    Ok(PrivateKeyDer::from_pem_file(path)?)
+}
+
+fn load_roots(path: &Path) -> TlsResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+#[derive(Clone)]
+pub struct TlsServer {
+    acceptor: TlsAcceptor,
+}
+
+impl TlsServer {
+    pub fn new(cert_path: &Path, key_path: &Path) -> TlsResult<Self> {
+        let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(load_certs(cert_path)?, load_private_key(key_path)?)?;
+        Ok(Self::from_config(config))
+    }
+
+    // Only clients presenting a certificate signed by the CA in client_ca_path are accepted
+    pub fn with_client_auth(
+        cert_path: &Path,
+        key_path: &Path,
+        client_ca_path: &Path,
+    ) -> TlsResult<Self> {
+        let provider = Arc::new(default_provider());
+        let verifier = WebPkiClientVerifier::builder_with_provider(
+            Arc::new(load_roots(client_ca_path)?),
+            Arc::clone(&provider),
+        )
+        .build()?;
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(load_certs(cert_path)?, load_private_key(key_path)?)?;
+        Ok(Self::from_config(config))
+    }
+
+    pub fn from_config(config: ServerConfig) -> Self {
+        Self {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        }
+    }
+
+    pub async fn accept(&self, stream: TcpStream) -> std::io::Result<ServerTlsStream<TcpStream>> {
+        self.acceptor.accept(stream).await
+    }
+}
+
+#[derive(Clone)]
+pub struct TlsClient {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+impl TlsClient {
+    pub fn new(ca_path: &Path, server_name: &str) -> TlsResult<Self> {
+        let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(load_roots(ca_path)?)
+            .with_no_client_auth();
+        Self::from_config(config, server_name)
+    }
+
+    pub fn with_identity(
+        ca_path: &Path,
+        server_name: &str,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> TlsResult<Self> {
+        let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(load_roots(ca_path)?)
+            .with_client_auth_cert(load_certs(cert_path)?, load_private_key(key_path)?)?;
+        Self::from_config(config, server_name)
+    }
+
+    pub fn from_config(config: ClientConfig, server_name: &str) -> TlsResult<Self> {
+        let server_name = ServerName::try_from(server_name.to_string())
+            .map_err(|_| TlsError::InvalidServerName(server_name.to_string()))?;
+        Ok(Self {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name,
+        })
+    }
+
+    pub async fn connect(&self, stream: TcpStream) -> std::io::Result<ClientTlsStream<TcpStream>> {
+        self.connector
+            .connect(self.server_name.clone(), stream)
+            .await
+    }
+}
@@ -0,0 +1,352 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::results::EncodingError;
+
+const SDCP_COMMAND_SETP: &str = "SETP";
+const SDCP_COMMAND_GETP: &str = "GETP";
+const SDCP_COMMAND_BEAT: &str = "BEAT";
+const SDCP_COMMAND_INFO: &str = "INFO";
+const SDCP_COMMAND_DISC: &str = "DISC";
+const SDCP_COMMAND_SUBS: &str = "SUBS";
+const SDCP_COMMAND_UNSU: &str = "UNSU";
+const SDCP_COMMAND_NOTI: &str = "NOTI";
+const SDCP_COMMAND_NONE: &str = "NONE";
+
+const SDCP_ENCODING_TEXT: &str = "TEXT";
+const SDCP_ENCODING_BINARY: &str = "BINARY";
+
+const SDCP_ERROR_UNSPECIFIED: &str = "UNSPECIFIED";
+const SDCP_ERROR_UNKNOWN_PARAMETER: &str = "UNKNOWN_PARAMETER";
+const SDCP_ERROR_INVALID_VALUE: &str = "INVALID_VALUE";
+const SDCP_ERROR_UNSUPPORTED_COMMAND: &str = "UNSUPPORTED_COMMAND";
+const SDCP_ERROR_INVALID_FRAME: &str = "INVALID_FRAME";
+const SDCP_ERROR_BUSY: &str = "BUSY";
+const SDCP_ERROR_INTERNAL: &str = "INTERNAL";
+
+const SDCP_RESULT_OK: &str = "OK";
+const SDCP_RESULT_FAILED: &str = "FAILED";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdcpCommand {
+    Setp,
+    Getp,
+    Beat,
+    Info,
+    Disc,
+    Subs,
+    Unsu,
+    Noti,
+    None,
+    Unknown(String),
+}
+
+impl FromStr for SdcpCommand {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let command = value.to_uppercase();
+        Ok(match command.as_str() {
+            SDCP_COMMAND_SETP => Self::Setp,
+            SDCP_COMMAND_GETP => Self::Getp,
+            SDCP_COMMAND_BEAT => Self::Beat,
+            SDCP_COMMAND_INFO => Self::Info,
+            SDCP_COMMAND_DISC => Self::Disc,
+            SDCP_COMMAND_SUBS => Self::Subs,
+            SDCP_COMMAND_UNSU => Self::Unsu,
+            SDCP_COMMAND_NOTI => Self::Noti,
+            SDCP_COMMAND_NONE => Self::None,
+            _ => Self::Unknown(command),
+        })
+    }
+}
+
+impl fmt::Display for SdcpCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Setp => f.write_str(SDCP_COMMAND_SETP),
+            Self::Getp => f.write_str(SDCP_COMMAND_GETP),
+            Self::Beat => f.write_str(SDCP_COMMAND_BEAT),
+            Self::Info => f.write_str(SDCP_COMMAND_INFO),
+            Self::Disc => f.write_str(SDCP_COMMAND_DISC),
+            Self::Subs => f.write_str(SDCP_COMMAND_SUBS),
+            Self::Unsu => f.write_str(SDCP_COMMAND_UNSU),
+            Self::Noti => f.write_str(SDCP_COMMAND_NOTI),
+            Self::None => f.write_str(SDCP_COMMAND_NONE),
+            Self::Unknown(command) => f.write_str(command),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdcpResult {
+    Ok,
+    Failed,
+    Unknown(String),
+}
+
+impl FromStr for SdcpResult {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let result = value.to_uppercase();
+        Ok(match result.as_str() {
+            SDCP_RESULT_OK => Self::Ok,
+            SDCP_RESULT_FAILED => Self::Failed,
+            _ => Self::Unknown(result),
+        })
+    }
+}
+
+impl fmt::Display for SdcpResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => f.write_str(SDCP_RESULT_OK),
+            Self::Failed => f.write_str(SDCP_RESULT_FAILED),
+            Self::Unknown(result) => f.write_str(result),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdcpErrorCode {
+    // FAILED from a peer that does not send error codes
+    Unspecified,
+    UnknownParameter,
+    InvalidValue,
+    UnsupportedCommand,
+    InvalidFrame,
+    Busy,
+    Internal,
+    Unknown(String),
+}
+
+impl FromStr for SdcpErrorCode {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let code = value.to_uppercase();
+        Ok(match code.as_str() {
+            SDCP_ERROR_UNSPECIFIED => Self::Unspecified,
+            SDCP_ERROR_UNKNOWN_PARAMETER => Self::UnknownParameter,
+            SDCP_ERROR_INVALID_VALUE => Self::InvalidValue,
+            SDCP_ERROR_UNSUPPORTED_COMMAND => Self::UnsupportedCommand,
+            SDCP_ERROR_INVALID_FRAME => Self::InvalidFrame,
+            SDCP_ERROR_BUSY => Self::Busy,
+            SDCP_ERROR_INTERNAL => Self::Internal,
+            _ => Self::Unknown(code),
+        })
+    }
+}
+
+impl fmt::Display for SdcpErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unspecified => f.write_str(SDCP_ERROR_UNSPECIFIED),
+            Self::UnknownParameter => f.write_str(SDCP_ERROR_UNKNOWN_PARAMETER),
+            Self::InvalidValue => f.write_str(SDCP_ERROR_INVALID_VALUE),
+            Self::UnsupportedCommand => f.write_str(SDCP_ERROR_UNSUPPORTED_COMMAND),
+            Self::InvalidFrame => f.write_str(SDCP_ERROR_INVALID_FRAME),
+            Self::Busy => f.write_str(SDCP_ERROR_BUSY),
+            Self::Internal => f.write_str(SDCP_ERROR_INTERNAL),
+            Self::Unknown(code) => f.write_str(code),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValueCase {
+    #[default]
+    Upper,
+    Preserve,
+}
+
+impl ValueCase {
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Self::Upper => value.to_uppercase(),
+            Self::Preserve => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadEncoding {
+    #[default]
+    Text,
+    Binary,
+}
+
+impl PayloadEncoding {
+    // A server grants the binary encoding only when it is configured for it, every
+    // other request falls back to the text one all peers understand
+    pub fn negotiate(&self, requested: &PayloadEncoding) -> PayloadEncoding {
+        match requested {
+            requested if requested == self => *requested,
+            _ => Self::Text,
+        }
+    }
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = EncodingError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_uppercase().as_str() {
+            SDCP_ENCODING_TEXT => Ok(Self::Text),
+            SDCP_ENCODING_BINARY => Ok(Self::Binary),
+            _ => Err(EncodingError),
+        }
+    }
+}
+
+impl fmt::Display for PayloadEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => f.write_str(SDCP_ENCODING_TEXT),
+            Self::Binary => f.write_str(SDCP_ENCODING_BINARY),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl ParamValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Self::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Self::Float(value) => Some(*value),
+            Self::Integer(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Text packets carry no types, so a string that would read back as another type
+    // travels in quotes: "true" stays a string, true is a Bool
+    pub fn to_text(&self) -> String {
+        match self {
+            Self::String(value) if !matches!(value.parse(), Ok(Self::String(_))) => {
+                format!("\"{}\"", value)
+            }
+            Self::String(value) if is_quoted(value) => format!("\"{}\"", value),
+            value => value.to_string(),
+        }
+    }
+    pub fn from_text(value: &str) -> Self {
+        if is_quoted(value) {
+            return Self::String(value[1..value.len() - 1].to_string());
+        }
+        let Ok(value) = value.parse();
+        value
+    }
+}
+
+fn is_quoted(value: &str) -> bool {
+    value.len() >= 2 && value.starts_with('"') && value.ends_with('"')
+}
+
+fn is_float_literal(value: &str) -> bool {
+    value.contains('.')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'))
+}
+
+// The type is guessed from the literal, so "12" is always an Integer; packets use
+// to_text() and from_text() to keep strings that look like numbers or booleans
+impl FromStr for ParamValue {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.eq_ignore_ascii_case("true") {
+            return Ok(Self::Bool(true));
+        }
+        if value.eq_ignore_ascii_case("false") {
+            return Ok(Self::Bool(false));
+        }
+        if let Ok(integer) = value.parse::<i64>() {
+            return Ok(Self::Integer(integer));
+        }
+        if is_float_literal(value) {
+            if let Ok(float) = value.parse::<f64>() {
+                return Ok(Self::Float(float));
+            }
+        }
+        Ok(Self::String(value.to_string()))
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Integer(value) => write!(f, "{}", value),
+            // Keep the decimal point so the value is read back as a float, not an integer
+            Self::Float(value) if value.is_finite() && value.fract() == 0.0 => {
+                write!(f, "{:.1}", value)
+            }
+            Self::Float(value) => write!(f, "{}", value),
+            Self::String(value) => f.write_str(value),
+        }
+    }
+}
+
+impl From<bool> for ParamValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i64> for ParamValue {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<u32> for ParamValue {
+    fn from(value: u32) -> Self {
+        Self::Integer(value as i64)
+    }
+}
+
+impl From<f64> for ParamValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<String> for ParamValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for ParamValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
@@ -0,0 +1,214 @@
+use bytes::BytesMut;
+use futures::stream::BoxStream;
+use std::time::Duration;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::codec::{Decoder, Encoder};
+use tokio_util::sync::CancellationToken;
+
+use crate::codec::{SdcpCodec, SdcpFrameCodec};
+use crate::results::{ConnectResult, FrameError, RecvError, SendError};
+use crate::retry::{is_transient, retry, RetryPolicy};
+use crate::{
+    ParamItem, PayloadEncoding, SdcpCommand, SdcpFrame, SdcpResult, ValueCase, SDCP_PACKET_HEADER,
+};
+
+use self::auth::{RejectedCounters, RejectedStats, SdcpuAuth, SdcpuVerifier};
+use self::devices::{DeviceFrame, DeviceKey, DeviceRegistry, DeviceSubscription};
+
+pub mod auth;
+pub mod devices;
+pub mod discovery;
+
+pub type SdcpFrameShared = Arc<Mutex<Box<SdcpFrame>>>;
+
+pub const SDCPU_ERRORS_CAPACITY: usize = 64;
+
+pub const SDCPU_MAX_DATAGRAM_SIZE: usize = 1024;
+
+const SDCPU_MIN_STALE_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+pub const SDCPU_STALE_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct SdcpuOptions {
+    pub retry_policy: RetryPolicy,
+    pub value_case: ValueCase,
+    pub encoding: PayloadEncoding,
+    pub auth: Option<SdcpuAuth>,
+    pub stale_after: Duration,
+}
+
+impl Default for SdcpuOptions {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::default(),
+            value_case: ValueCase::default(),
+            encoding: PayloadEncoding::default(),
+            auth: None,
+            stale_after: SDCPU_STALE_AFTER,
+        }
+    }
+}
+
+impl SdcpuOptions {
+    fn frame_codec(&self) -> SdcpFrameCodec {
+        SdcpFrameCodec::new(SdcpCodec::default(), self.encoding, self.value_case)
+    }
+}
+
+pub struct SdcpuHandler {
+    address: SocketAddr,
+    errors: Option<mpsc::Receiver<FrameError>>,
+    rejected: Arc<RejectedCounters>,
+    devices: DeviceRegistry,
+    shutdown: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl SdcpuHandler {
+    pub async fn new(
+        address: SocketAddr,
+        shared_frame: SdcpFrameShared,
+        options: SdcpuOptions,
+    ) -> ConnectResult<Self> {
+        let socket = retry(options.retry_policy, is_transient, || {
+            UdpSocket::bind(address)
+        })
+        .await?;
+        let address = socket.local_addr()?;
+        let (errors, errors_receiver) = mpsc::channel(SDCPU_ERRORS_CAPACITY);
+        let rejected = Arc::new(RejectedCounters::default());
+        let counters = Arc::clone(&rejected);
+        let mut codec = options.frame_codec();
+        let mut verifier = options.auth.map(SdcpuVerifier::new);
+        let devices = DeviceRegistry::new(options.stale_after);
+        let mut stale_check =
+            tokio::time::interval((options.stale_after / 2).max(SDCPU_MIN_STALE_CHECK_INTERVAL));
+        let registry = devices.clone();
+        let shutdown = CancellationToken::new();
+        let receiver_shutdown = shutdown.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let mut datagram = [0; SDCPU_MAX_DATAGRAM_SIZE];
+                let received = tokio::select! {
+                    _ = receiver_shutdown.cancelled() => break,
+                    _ = stale_check.tick() => {
+                        registry.mark_stale();
+                        continue;
+                    }
+                    received = socket.recv_from(&mut datagram) => received,
+                };
+                match received {
+                    Ok(response) => {
+                        let datagram = &datagram[..response.0];
+                        let frame = match verifier.as_mut() {
+                            Some(verifier) => verifier.verify(datagram, &mut codec),
+                            None => read_datagram(datagram, &mut codec).map(|(frame, _)| frame),
+                        };
+                        match frame {
+                            Ok(frame) => {
+                                registry.update(frame.clone(), response.1);
+                                **shared_frame.lock().unwrap() = frame;
+                            }
+                            // Rejected datagrams keep the last good frame, flagged as invalid
+                            Err(FrameError::Auth(error)) => {
+                                counters.record(&error);
+                                registry.invalidate(response.1);
+                                let _ = errors.try_send(FrameError::Auth(error));
+                            }
+                            Err(error) => {
+                                registry.invalidate(response.1);
+                                let _ = errors.try_send(error);
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        let _ = errors.try_send(FrameError::Recv(RecvError::Io(error)));
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            address,
+            errors: Some(errors_receiver),
+            rejected,
+            devices,
+            shutdown,
+            task,
+        })
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn take_errors(&mut self) -> Option<mpsc::Receiver<FrameError>> {
+        self.errors.take()
+    }
+
+    pub fn rejected(&self) -> RejectedStats {
+        self.rejected.stats()
+    }
+
+    pub fn devices(&self) -> Vec<DeviceKey> {
+        self.devices.devices()
+    }
+
+    pub fn device(&self, key: &DeviceKey) -> Option<DeviceFrame> {
+        self.devices.device(key)
+    }
+
+    // Subscribing to a device that has not reported yet is allowed, it starts out as None
+    pub fn subscribe(&self, key: DeviceKey) -> DeviceSubscription {
+        self.devices.subscribe(key)
+    }
+
+    pub fn frames(&self) -> BoxStream<'static, DeviceFrame> {
+        self.devices.frames()
+    }
+
+    pub async fn shutdown(self) {
+        self.shutdown.cancel();
+        let _ = self.task.await;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+// A datagram carries exactly one frame, encoded the same way as on a TCP connection
+pub fn make_datagram(frame: SdcpFrame, codec: &mut SdcpFrameCodec) -> Result<Vec<u8>, SendError> {
+    let mut datagram = BytesMut::new();
+    codec.encode(frame, &mut datagram)?;
+    Ok(datagram.to_vec())
+}
+
+// Returns the frame and the number of bytes it took, anything after it is the signature
+pub fn read_datagram(
+    datagram: &[u8],
+    codec: &mut SdcpFrameCodec,
+) -> Result<(SdcpFrame, usize), FrameError> {
+    let mut src = BytesMut::from(datagram);
+    match codec.decode(&mut src)? {
+        Some(frame) => Ok((frame?, datagram.len() - src.len())),
+        None => Err(RecvError::InvalidPacket.into()),
+    }
+}
+
+// Telemetry is not a request, so it carries no id and no command
+pub fn make_telemetry_frame(parameters: Vec<ParamItem>) -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::None,
+        parameters,
+        result: SdcpResult::Ok,
+    }
+}
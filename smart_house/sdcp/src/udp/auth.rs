@@ -0,0 +1,209 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::codec::SdcpFrameCodec;
+use crate::results::{AuthError, FrameResult, SendError};
+use crate::udp::{make_datagram, read_datagram};
+use crate::{ParamItem, ParamValue, PayloadEncoding, SdcpFrame, SDCP_PARAM_DEVICE};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SDCPU_PARAM_SEQUENCE: &str = "SEQ";
+pub const SDCPU_PARAM_TIMESTAMP: &str = "TS";
+
+pub const SDCPU_MAX_AGE: Duration = Duration::from_secs(30);
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn mac_for(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+pub struct SdcpuSigner {
+    device: String,
+    key: Vec<u8>,
+    sequence: u64,
+    codec: SdcpFrameCodec,
+}
+
+impl SdcpuSigner {
+    pub fn new(device: String, key: Vec<u8>) -> Self {
+        // Starting from the clock keeps sequence numbers growing across sender restarts
+        Self {
+            device,
+            key,
+            sequence: unix_millis(),
+            codec: SdcpFrameCodec::default(),
+        }
+    }
+
+    pub fn set_encoding(&mut self, encoding: PayloadEncoding) {
+        self.codec.set_encoding(encoding);
+    }
+
+    // The MAC covers the whole encoded frame and is appended to it as raw bytes
+    pub fn sign(&mut self, frame: &SdcpFrame) -> Result<Vec<u8>, SendError> {
+        self.sequence += 1;
+        let mut signed = frame.clone();
+        signed.parameters.splice(
+            0..0,
+            [
+                ParamItem::new(
+                    SDCP_PARAM_DEVICE.to_string(),
+                    ParamValue::String(self.device.clone()),
+                ),
+                ParamItem::new(
+                    SDCPU_PARAM_SEQUENCE.to_string(),
+                    ParamValue::Integer(self.sequence as i64),
+                ),
+                ParamItem::new(
+                    SDCPU_PARAM_TIMESTAMP.to_string(),
+                    ParamValue::Integer(unix_millis() as i64),
+                ),
+            ],
+        );
+        let mut datagram = make_datagram(signed, &mut self.codec)?;
+        let mac = mac_for(&self.key, &datagram).finalize().into_bytes();
+        datagram.extend_from_slice(&mac);
+        Ok(datagram)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SdcpuAuth {
+    keys: HashMap<String, Vec<u8>>,
+    max_age: Duration,
+}
+
+impl SdcpuAuth {
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            keys: HashMap::new(),
+            max_age,
+        }
+    }
+
+    pub fn add_key(&mut self, device: &str, key: Vec<u8>) {
+        self.keys.insert(device.to_uppercase(), key);
+    }
+}
+
+impl Default for SdcpuAuth {
+    fn default() -> Self {
+        Self::new(SDCPU_MAX_AGE)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RejectedCounters {
+    unsigned: AtomicU64,
+    unknown_device: AtomicU64,
+    bad_signature: AtomicU64,
+    replayed: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectedStats {
+    pub unsigned: u64,
+    pub unknown_device: u64,
+    pub bad_signature: u64,
+    pub replayed: u64,
+}
+
+impl RejectedStats {
+    pub fn total(&self) -> u64 {
+        self.unsigned + self.unknown_device + self.bad_signature + self.replayed
+    }
+}
+
+impl RejectedCounters {
+    pub fn record(&self, error: &AuthError) {
+        let counter = match error {
+            AuthError::Unsigned => &self.unsigned,
+            AuthError::UnknownDevice(_) => &self.unknown_device,
+            AuthError::BadSignature => &self.bad_signature,
+            AuthError::Replayed | AuthError::Stale => &self.replayed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> RejectedStats {
+        RejectedStats {
+            unsigned: self.unsigned.load(Ordering::Relaxed),
+            unknown_device: self.unknown_device.load(Ordering::Relaxed),
+            bad_signature: self.bad_signature.load(Ordering::Relaxed),
+            replayed: self.replayed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub(crate) struct SdcpuVerifier {
+    auth: SdcpuAuth,
+    sequences: HashMap<String, u64>,
+}
+
+impl SdcpuVerifier {
+    pub(crate) fn new(auth: SdcpuAuth) -> Self {
+        Self {
+            auth,
+            sequences: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn verify(&mut self, datagram: &[u8], codec: &mut SdcpFrameCodec) -> FrameResult {
+        let (frame, length) = read_datagram(datagram, codec)?;
+        let (signed, mac) = datagram.split_at(length);
+        if mac.is_empty() {
+            return Err(AuthError::Unsigned.into());
+        }
+        let param = |name: &str| {
+            frame
+                .parameters
+                .iter()
+                .find(|item| item.name.eq(name))
+                .map(|item| item.value.clone())
+        };
+        let device = param(SDCP_PARAM_DEVICE)
+            .ok_or(AuthError::Unsigned)?
+            .to_string()
+            .to_uppercase();
+        let key = self
+            .auth
+            .keys
+            .get(&device)
+            .ok_or_else(|| AuthError::UnknownDevice(device.clone()))?;
+        mac_for(key, signed)
+            .verify_slice(mac)
+            .map_err(|_| AuthError::BadSignature)?;
+
+        let sequence = param(SDCPU_PARAM_SEQUENCE)
+            .and_then(|value| value.as_integer())
+            .ok_or(AuthError::BadSignature)? as u64;
+        let timestamp = param(SDCPU_PARAM_TIMESTAMP)
+            .and_then(|value| value.as_integer())
+            .ok_or(AuthError::BadSignature)? as u64;
+        if unix_millis().abs_diff(timestamp) > self.auth.max_age.as_millis() as u64 {
+            return Err(AuthError::Stale.into());
+        }
+        if self
+            .sequences
+            .get(&device)
+            .is_some_and(|last| sequence <= *last)
+        {
+            return Err(AuthError::Replayed.into());
+        }
+        self.sequences.insert(device, sequence);
+
+        Ok(frame)
+    }
+}
@@ -0,0 +1,175 @@
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+
+use crate::{SdcpFrame, SDCP_PARAM_DEVICE};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceKey {
+    Address(SocketAddr),
+    Device(String),
+}
+
+impl DeviceKey {
+    pub fn device(id: &str) -> Self {
+        Self::Device(id.to_uppercase())
+    }
+
+    // Frames naming their device are keyed by it, the others by the sender address
+    pub fn for_frame(frame: &SdcpFrame, address: SocketAddr) -> Self {
+        match frame
+            .parameters
+            .iter()
+            .find(|item| item.name.eq(SDCP_PARAM_DEVICE))
+        {
+            Some(item) => Self::device(&item.value.to_string()),
+            None => Self::Address(address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceStatus {
+    Online,
+    // Nothing was received within the stale window, the frame is the last good one
+    Stale,
+    // The last datagram from the device was rejected, the frame is the last good one
+    Invalid,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceFrame {
+    pub frame: SdcpFrame,
+    pub address: SocketAddr,
+    pub last_seen: Instant,
+    pub status: DeviceStatus,
+}
+
+impl DeviceFrame {
+    pub fn age(&self) -> Duration {
+        self.last_seen.elapsed()
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.status == DeviceStatus::Online
+    }
+}
+
+pub type DeviceSubscription = watch::Receiver<Option<DeviceFrame>>;
+
+pub const SDCPU_FRAMES_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone)]
+pub(crate) struct DeviceRegistry {
+    devices: Arc<Mutex<HashMap<DeviceKey, watch::Sender<Option<DeviceFrame>>>>>,
+    frames: broadcast::Sender<DeviceFrame>,
+    stale_after: Duration,
+}
+
+impl DeviceRegistry {
+    pub(crate) fn new(stale_after: Duration) -> Self {
+        Self {
+            devices: Arc::new(Mutex::new(HashMap::new())),
+            frames: broadcast::channel(SDCPU_FRAMES_CAPACITY).0,
+            stale_after,
+        }
+    }
+
+    pub(crate) fn update(&self, frame: SdcpFrame, address: SocketAddr) {
+        let key = DeviceKey::for_frame(&frame, address);
+        let device = DeviceFrame {
+            frame,
+            address,
+            last_seen: Instant::now(),
+            status: DeviceStatus::Online,
+        };
+        let _ = self.frames.send(device.clone());
+        self.devices
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| watch::channel(None).0)
+            .send_replace(Some(device));
+    }
+
+    pub(crate) fn invalidate(&self, address: SocketAddr) {
+        for device in self.devices.lock().unwrap().values() {
+            device.send_if_modified(|device| match device {
+                Some(device) if device.address == address && device.is_online() => {
+                    device.status = DeviceStatus::Invalid;
+                    let _ = self.frames.send(device.clone());
+                    true
+                }
+                _ => false,
+            });
+        }
+    }
+
+    pub(crate) fn mark_stale(&self) {
+        for device in self.devices.lock().unwrap().values() {
+            device.send_if_modified(|device| match device {
+                Some(device)
+                    if device.status != DeviceStatus::Stale && device.age() > self.stale_after =>
+                {
+                    device.status = DeviceStatus::Stale;
+                    let _ = self.frames.send(device.clone());
+                    true
+                }
+                _ => false,
+            });
+        }
+    }
+
+    pub(crate) fn devices(&self) -> Vec<DeviceKey> {
+        self.devices
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, device)| device.borrow().is_some())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub(crate) fn device(&self, key: &DeviceKey) -> Option<DeviceFrame> {
+        self.devices
+            .lock()
+            .unwrap()
+            .get(key)
+            .and_then(|device| device.borrow().clone())
+            .map(|mut device| {
+                // The periodic check may not have run yet
+                if device.age() > self.stale_after {
+                    device.status = DeviceStatus::Stale;
+                }
+                device
+            })
+    }
+
+    pub(crate) fn subscribe(&self, key: DeviceKey) -> DeviceSubscription {
+        self.devices
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| watch::channel(None).0)
+            .subscribe()
+    }
+
+    // Carries every good frame and every switch to Stale or Invalid, so consumers need no
+    // timers of their own; ends when the receiver stops, a consumer that falls behind skips
+    // the frames it missed
+    pub(crate) fn frames(&self) -> BoxStream<'static, DeviceFrame> {
+        stream::unfold(self.frames.subscribe(), |mut frames| async move {
+            loop {
+                match frames.recv().await {
+                    Ok(frame) => return Some((frame, frames)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed()
+    }
+}
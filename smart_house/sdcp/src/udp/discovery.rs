@@ -0,0 +1,312 @@
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::codec::SdcpFrameCodec;
+use crate::results::ConnectResult;
+use crate::udp::{make_datagram, read_datagram, SDCPU_MAX_DATAGRAM_SIZE};
+use crate::{
+    ParamItem, ParamValue, SdcpCommand, SdcpFrame, SdcpResult, SDCP_PACKET_HEADER,
+    SDCP_PARAM_ADDRESS, SDCP_PARAM_CAPABILITIES, SDCP_PARAM_DEVICE, SDCP_PARAM_TYPE,
+};
+
+pub const SDCP_DISCOVERY_PORT: u16 = 4200;
+
+pub const SDCP_DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 99);
+
+pub const SDCP_DISCOVERY_WAIT: Duration = Duration::from_millis(500);
+
+pub const SDCP_TYPE_SOCKET: &str = "SOCKET";
+pub const SDCP_TYPE_THERMOMETER: &str = "THERMOMETER";
+
+const SDCP_CAPABILITIES_SEPARATOR: &str = ",";
+
+const SDCP_DISCOVERY_INTERFACES: [Ipv4Addr; 2] = [Ipv4Addr::UNSPECIFIED, Ipv4Addr::LOCALHOST];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub device: String,
+    pub device_type: String,
+    pub address: SocketAddr,
+    pub capabilities: Vec<String>,
+}
+
+impl DeviceInfo {
+    pub fn new(
+        device: String,
+        device_type: String,
+        address: SocketAddr,
+        capabilities: Vec<String>,
+    ) -> Self {
+        Self {
+            device,
+            device_type,
+            address,
+            capabilities,
+        }
+    }
+
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|item| item.eq_ignore_ascii_case(capability))
+    }
+
+    pub fn frame(&self) -> SdcpFrame {
+        discovery_frame(vec![
+            ParamItem::new(
+                SDCP_PARAM_DEVICE.to_string(),
+                ParamValue::String(self.device.clone()),
+            ),
+            ParamItem::new(
+                SDCP_PARAM_TYPE.to_string(),
+                ParamValue::String(self.device_type.clone()),
+            ),
+            ParamItem::new(
+                SDCP_PARAM_ADDRESS.to_string(),
+                ParamValue::String(self.address.to_string()),
+            ),
+            ParamItem::new(
+                SDCP_PARAM_CAPABILITIES.to_string(),
+                ParamValue::String(self.capabilities.join(SDCP_CAPABILITIES_SEPARATOR)),
+            ),
+        ])
+    }
+
+    // Replies missing the identity or the address are not usable and are skipped
+    pub fn from_frame(frame: &SdcpFrame) -> Option<Self> {
+        if frame.command != SdcpCommand::Disc {
+            return None;
+        }
+        let param = |name: &str| {
+            frame
+                .parameters
+                .iter()
+                .find(|item| item.name.eq(name))
+                .map(|item| item.value.to_string())
+        };
+        Some(Self {
+            device: param(SDCP_PARAM_DEVICE)?,
+            device_type: param(SDCP_PARAM_TYPE).unwrap_or_default(),
+            address: param(SDCP_PARAM_ADDRESS)?.parse().ok()?,
+            capabilities: param(SDCP_PARAM_CAPABILITIES)
+                .unwrap_or_default()
+                .split(SDCP_CAPABILITIES_SEPARATOR)
+                .filter(|item| !item.is_empty())
+                .map(|item| item.to_string())
+                .collect(),
+        })
+    }
+}
+
+fn discovery_frame(parameters: Vec<ParamItem>) -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::Disc,
+        parameters,
+        result: SdcpResult::Ok,
+    }
+}
+
+// A probe may name a device type, only devices of that type answer it
+pub fn make_probe(device_type: Option<&str>) -> SdcpFrame {
+    discovery_frame(
+        device_type
+            .map(|device_type| {
+                ParamItem::new(
+                    SDCP_PARAM_TYPE.to_string(),
+                    ParamValue::String(device_type.to_string()),
+                )
+            })
+            .into_iter()
+            .collect(),
+    )
+}
+
+// Returns the reply datagram when the datagram is a probe this device has to answer
+pub fn discovery_reply(
+    datagram: &[u8],
+    info: &DeviceInfo,
+    codec: &mut SdcpFrameCodec,
+) -> Option<Vec<u8>> {
+    let (probe, _) = read_datagram(datagram, codec).ok()?;
+    // Replies are sent with the same command, they carry a DEVICE and must not be answered
+    if probe.command != SdcpCommand::Disc
+        || probe
+            .parameters
+            .iter()
+            .any(|item| item.name.eq(SDCP_PARAM_DEVICE))
+    {
+        return None;
+    }
+    let wanted = probe
+        .parameters
+        .iter()
+        .find(|item| item.name.eq(SDCP_PARAM_TYPE));
+    if wanted.is_some_and(|item| {
+        !item
+            .value
+            .to_string()
+            .eq_ignore_ascii_case(&info.device_type)
+    }) {
+        return None;
+    }
+    make_datagram(info.frame(), codec).ok()
+}
+
+// Several devices on one host share the discovery port, so the address is reused.
+// A multicast group address binds the port on all interfaces and joins that group,
+// any other address on the discovery port joins the discovery group.
+pub fn discovery_socket(address: SocketAddr) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(true)?;
+    match address.ip() {
+        IpAddr::V4(group) if group.is_multicast() => {
+            let any = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), address.port());
+            socket.bind(&any.into())?;
+            join_group(&socket, &group)?;
+        }
+        IpAddr::V4(_) if address.port() == SDCP_DISCOVERY_PORT => {
+            socket.bind(&address.into())?;
+            join_group(&socket, &SDCP_DISCOVERY_GROUP)?;
+        }
+        _ => socket.bind(&address.into())?,
+    }
+    Ok(socket.into())
+}
+
+// Joins on the default interface and on loopback, so devices on this host are found
+// even without a multicast route; one of the two is enough
+fn join_group(socket: &Socket, group: &Ipv4Addr) -> io::Result<()> {
+    let mut joined = false;
+    let mut last_error = None;
+    for interface in SDCP_DISCOVERY_INTERFACES {
+        match socket.join_multicast_v4(group, &interface) {
+            Ok(()) => joined = true,
+            Err(error) => last_error = Some(error),
+        }
+    }
+    match (joined, last_error) {
+        (false, Some(error)) => Err(error),
+        _ => Ok(()),
+    }
+}
+
+pub struct DiscoveryResponder {
+    address: SocketAddr,
+    shutdown: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl DiscoveryResponder {
+    pub async fn bind(address: SocketAddr, info: DeviceInfo) -> ConnectResult<Self> {
+        let socket = discovery_socket(address)?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket)?;
+        let address = socket.local_addr()?;
+        let shutdown = CancellationToken::new();
+        let responder_shutdown = shutdown.clone();
+        let task = tokio::spawn(async move {
+            let mut codec = SdcpFrameCodec::default();
+            loop {
+                let mut datagram = [0; SDCPU_MAX_DATAGRAM_SIZE];
+                let received = tokio::select! {
+                    _ = responder_shutdown.cancelled() => break,
+                    received = socket.recv_from(&mut datagram) => received,
+                };
+                // Discovery is best effort, anything but a valid probe is ignored
+                if let Ok((length, sender)) = received {
+                    if let Some(reply) = discovery_reply(&datagram[..length], &info, &mut codec) {
+                        let _ = socket.send_to(&reply, sender).await;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            address,
+            shutdown,
+            task,
+        })
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub async fn shutdown(self) {
+        self.shutdown.cancel();
+        let _ = self.task.await;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+// Probes every target (unicast, broadcast or multicast) and collects the replies
+// that arrive within the wait time, one entry per device
+pub async fn discover(
+    targets: &[SocketAddr],
+    device_type: Option<&str>,
+    wait: Duration,
+) -> ConnectResult<Vec<DeviceInfo>> {
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)).await?;
+    socket.set_broadcast(true)?;
+    let mut codec = SdcpFrameCodec::default();
+    let probe = make_datagram(make_probe(device_type), &mut codec).map_err(io::Error::other)?;
+    // An unreachable target, e.g. a group without a multicast route, must not hide the others
+    let mut sent = false;
+    let mut last_error = None;
+    for target in targets {
+        // A group is probed on the same interfaces the responders join it on
+        let interfaces: &[Ipv4Addr] = match target.ip() {
+            IpAddr::V4(group) if group.is_multicast() => &SDCP_DISCOVERY_INTERFACES,
+            _ => &[Ipv4Addr::UNSPECIFIED],
+        };
+        for interface in interfaces {
+            let result = match SockRef::from(&socket).set_multicast_if_v4(interface) {
+                Ok(()) => socket.send_to(&probe, target).await,
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(_) => sent = true,
+                Err(error) => last_error = Some(error),
+            }
+        }
+    }
+    if let (false, Some(error)) = (sent, last_error) {
+        return Err(error.into());
+    }
+
+    let mut devices: HashMap<String, DeviceInfo> = HashMap::new();
+    let deadline = tokio::time::Instant::now() + wait;
+    loop {
+        let mut datagram = [0; SDCPU_MAX_DATAGRAM_SIZE];
+        let length = match tokio::time::timeout_at(deadline, socket.recv_from(&mut datagram)).await
+        {
+            Ok(Ok((length, _))) => length,
+            // A unicast target without a responder reports itself as a refused connection
+            Ok(Err(_)) => continue,
+            Err(_) => break,
+        };
+        if let Some(info) = read_datagram(&datagram[..length], &mut codec)
+            .ok()
+            .and_then(|(frame, _)| DeviceInfo::from_frame(&frame))
+        {
+            devices.insert(info.device.clone(), info);
+        }
+    }
+    let mut devices: Vec<DeviceInfo> = devices.into_values().collect();
+    devices.sort_by(|first, second| first.device.cmp(&second.device));
+    Ok(devices)
+}
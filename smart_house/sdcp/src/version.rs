@@ -0,0 +1,77 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::results::VersionError;
+
+pub const SDCP_PROTOCOL: &str = "SDCP";
+
+// Every peer understands the base version, so it is used until a session negotiates another one
+pub const SDCP_BASE_VERSION: SdcpVersion = SdcpVersion::new(0, 0, 1);
+pub const SDCP_VERSION: SdcpVersion = SdcpVersion::new(0, 1, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SdcpVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl SdcpVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    // Peers with the same major version are compatible and talk using the lower of the two versions
+    pub fn is_compatible(&self, other: &SdcpVersion) -> bool {
+        self.major == other.major
+    }
+
+    pub fn negotiate(&self, peer: &SdcpVersion) -> Option<SdcpVersion> {
+        if self.is_compatible(peer) {
+            Some(*self.min(peer))
+        } else {
+            None
+        }
+    }
+
+    pub fn header(&self) -> String {
+        format!("{} {}", SDCP_PROTOCOL, self)
+    }
+}
+
+impl Default for SdcpVersion {
+    fn default() -> Self {
+        SDCP_VERSION
+    }
+}
+
+impl FromStr for SdcpVersion {
+    type Err = VersionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parts = value
+            .split('.')
+            .map(|part| {
+                if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) {
+                    part.parse::<u32>().map_err(|_| VersionError)
+                } else {
+                    Err(VersionError)
+                }
+            })
+            .collect::<Result<Vec<u32>, VersionError>>()?;
+        match parts[..] {
+            [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+            _ => Err(VersionError),
+        }
+    }
+}
+
+impl fmt::Display for SdcpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
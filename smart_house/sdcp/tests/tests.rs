@@ -0,0 +1,1585 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::StreamExt;
+use proptest::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "tls")]
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Decoder, Encoder};
+
+use sdcp::batch::BatchReport;
+use sdcp::codec::{SdcpCodec, SdcpFrameCodec};
+use sdcp::device_error::DeviceError;
+use sdcp::encoding::{make_binary_frame, make_binary_packet};
+use sdcp::escape::{escape, unescape};
+use sdcp::events::SdcpNotifier;
+use sdcp::results::{
+    ConnError, FrameError, FrameResult, RecvError, RequestError, SendError, ServerError,
+    SessionError,
+};
+use sdcp::retry::RetryPolicy;
+use sdcp::session::{SdcpSession, SessionOptions, SessionTimeouts};
+#[cfg(feature = "tls")]
+use sdcp::tls::{TlsClient, TlsServer};
+use sdcp::udp::auth::{RejectedStats, SdcpuAuth, SdcpuSigner};
+use sdcp::udp::devices::{DeviceKey, DeviceStatus};
+use sdcp::udp::discovery::{
+    discover, DeviceInfo, DiscoveryResponder, SDCP_DISCOVERY_GROUP, SDCP_DISCOVERY_PORT,
+};
+use sdcp::udp::{
+    make_datagram, make_telemetry_frame, read_datagram, SdcpFrameShared, SdcpuHandler, SdcpuOptions,
+};
+use sdcp::{
+    make_frame, make_frame_with_case, make_packet, ParamItem, ParamValue, PayloadEncoding,
+    SdcpCommand, SdcpErrorCode, SdcpFrame, SdcpHandler, SdcpRequestHandler, SdcpResult,
+    SdcpVersion, ValueCase, SDCP_BASE_VERSION, SDCP_PACKET_HEADER, SDCP_VERSION,
+};
+
+async fn echo_handler(frame: FrameResult) -> SdcpFrame {
+    match frame {
+        Ok(frame) => SdcpFrame {
+            protocol: SDCP_PACKET_HEADER.to_string(),
+            id: None,
+            command: frame.command,
+            parameters: frame.parameters,
+            result: SdcpResult::Ok,
+        },
+        Err(_) => SdcpFrame {
+            protocol: SDCP_PACKET_HEADER.to_string(),
+            id: None,
+            command: SdcpCommand::None,
+            parameters: vec![],
+            result: SdcpResult::Failed,
+        },
+    }
+}
+
+fn local_address(port: u16) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port)
+}
+
+fn getp_frame(name: &str) -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::Getp,
+        parameters: vec![ParamItem::new(name.to_string(), ParamValue::from(""))],
+        result: SdcpResult::Ok,
+    }
+}
+
+async fn start_server() -> SocketAddr {
+    let address = local_address(0);
+    SdcpHandler::new(address)
+        .bind(echo_handler)
+        .await
+        .unwrap()
+        .local_address()
+}
+
+#[tokio::test]
+async fn test_session_multiple_requests() {
+    let address = start_server().await;
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+
+    for name in ["STATUS", "PWRCON", "STATUS"] {
+        let response = session.request(getp_frame(name)).await.unwrap();
+        assert_eq!(response.result, SdcpResult::Ok);
+        assert_eq!(response.parameters[0].name, name);
+    }
+    assert!(!session.is_closed());
+}
+
+#[tokio::test]
+async fn test_session_concurrent_requests_matched_by_id() {
+    let address = start_server().await;
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+
+    let (first, second) = tokio::join!(
+        session.request(getp_frame("FIRST")),
+        session.request(getp_frame("SECOND"))
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert_eq!(first.parameters[0].name, "FIRST");
+    assert_eq!(second.parameters[0].name, "SECOND");
+    assert_ne!(first.id, second.id);
+}
+
+#[tokio::test]
+async fn test_session_keepalive() {
+    let address = start_server().await;
+    let session = SdcpSession::connect(
+        address,
+        SessionOptions {
+            keepalive: Some(Duration::from_millis(50)),
+            ..SessionOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!session.is_closed());
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+}
+
+#[tokio::test]
+async fn test_one_shot_request() {
+    let address = start_server().await;
+    let handler = SdcpHandler::new(local_address(0));
+
+    let response = handler
+        .request(getp_frame("STATUS"), address)
+        .await
+        .unwrap();
+    assert_eq!(response.command, SdcpCommand::Getp);
+    assert_eq!(response.id, None);
+}
+
+#[test]
+fn test_codec_decodes_packet_split_across_reads() {
+    let mut codec = SdcpCodec::default();
+    let mut encoded = BytesMut::new();
+    let data = "Command=INFO;Result=OK;".repeat(500);
+    codec.encode(data.as_str(), &mut encoded).unwrap();
+
+    let mut buffer = BytesMut::new();
+    let mut decoded = None;
+    for chunk in encoded.chunks(7) {
+        assert!(decoded.is_none());
+        buffer.extend_from_slice(chunk);
+        decoded = codec.decode(&mut buffer).unwrap();
+    }
+    assert_eq!(decoded.unwrap(), data);
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_codec_decodes_consecutive_packets() {
+    let mut codec = SdcpCodec::default();
+    let mut buffer = BytesMut::new();
+    codec.encode("Command=BEAT;", &mut buffer).unwrap();
+    codec.encode("Command=INFO;", &mut buffer).unwrap();
+
+    assert_eq!(codec.decode(&mut buffer).unwrap().unwrap(), "Command=BEAT;");
+    assert_eq!(codec.decode(&mut buffer).unwrap().unwrap(), "Command=INFO;");
+    assert!(codec.decode(&mut buffer).unwrap().is_none());
+}
+
+#[test]
+fn test_codec_rejects_oversize_frames() {
+    let mut codec = SdcpCodec::new(16);
+    let mut buffer = BytesMut::new();
+    assert!(matches!(
+        codec.encode("Command=INFO;Result=OK;", &mut buffer),
+        Err(SendError::FrameTooLarge(23))
+    ));
+
+    SdcpCodec::default()
+        .encode("Command=INFO;Result=OK;", &mut buffer)
+        .unwrap();
+    assert!(matches!(
+        codec.decode(&mut buffer),
+        Err(RecvError::FrameTooLarge(23))
+    ));
+}
+
+#[test]
+fn test_codec_rejects_unknown_header() {
+    let mut codec = SdcpCodec::default();
+    let mut buffer = BytesMut::from(&b"HTTP 1.1.0\n\0\0\0\0"[..]);
+    assert!(matches!(
+        codec.decode(&mut buffer),
+        Err(RecvError::InvalidPacket)
+    ));
+}
+
+#[tokio::test]
+async fn test_stalled_client_does_not_block_others() {
+    let address = start_server().await;
+    let _stalled = TcpStream::connect(address).await.unwrap();
+
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    let response = tokio::time::timeout(
+        Duration::from_secs(1),
+        session.request(getp_frame("STATUS")),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+}
+
+#[tokio::test]
+async fn test_connection_limit() {
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_max_connections(1);
+    let server = handler.bind(echo_handler).await.unwrap();
+    let address = server.local_address();
+
+    let first = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    first.request(getp_frame("STATUS")).await.unwrap();
+
+    // The handshake cannot complete until the server accepts the connection
+    let second = tokio::spawn(SdcpSession::connect(address, SessionOptions::default()));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!second.is_finished());
+
+    drop(first);
+    let second = tokio::time::timeout(Duration::from_secs(1), second)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let response = second.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+}
+
+#[tokio::test]
+async fn test_idle_connection_read_timeout() {
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_idle_timeout(Some(Duration::from_millis(100)));
+    let server = handler.bind(echo_handler).await.unwrap();
+    let address = server.local_address();
+
+    let mut stalled = TcpStream::connect(address).await.unwrap();
+    let mut buffer = [0; 16];
+    let read = tokio::time::timeout(Duration::from_secs(1), stalled.read(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_server_shutdown() {
+    let server = SdcpHandler::new(local_address(0))
+        .bind(echo_handler)
+        .await
+        .unwrap();
+    let address = server.local_address();
+
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    session.request(getp_frame("STATUS")).await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), server.shutdown())
+        .await
+        .unwrap();
+    assert!(session.request(getp_frame("STATUS")).await.is_err());
+    assert!(TcpStream::connect(address).await.is_err());
+}
+
+struct CountingHandler {
+    requests: Arc<Mutex<u32>>,
+}
+
+#[async_trait]
+impl SdcpRequestHandler for CountingHandler {
+    async fn handle(&self, frame: FrameResult) -> SdcpFrame {
+        *self.requests.lock().unwrap() += 1;
+        echo_handler(frame).await
+    }
+}
+
+#[tokio::test]
+async fn test_stateful_handler() {
+    let requests = Arc::new(Mutex::new(0));
+    let handler = CountingHandler {
+        requests: Arc::clone(&requests),
+    };
+    let response = handler.handle(Ok(getp_frame("STATUS"))).await;
+    assert_eq!(response.result, SdcpResult::Ok);
+    assert_eq!(*requests.lock().unwrap(), 1);
+
+    let server = SdcpHandler::new(local_address(0))
+        .bind(handler)
+        .await
+        .unwrap();
+    let address = server.local_address();
+
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    session.request(getp_frame("STATUS")).await.unwrap();
+    session.request(getp_frame("PWRCON")).await.unwrap();
+    assert_eq!(*requests.lock().unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_closure_handler() {
+    let prefix = Arc::new(String::from("CLOSURE"));
+    let server = SdcpHandler::new(local_address(0))
+        .bind(move |frame: FrameResult| {
+            let prefix = Arc::clone(&prefix);
+            async move {
+                let mut response = echo_handler(frame).await;
+                response.parameters[0].value = ParamValue::from(prefix.as_str());
+                response
+            }
+        })
+        .await
+        .unwrap();
+
+    let session = SdcpSession::connect(server.local_address(), SessionOptions::default())
+        .await
+        .unwrap();
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.parameters[0].value, ParamValue::from("CLOSURE"));
+}
+
+#[tokio::test]
+async fn test_bind_reports_address_in_use() {
+    let address = start_server().await;
+    let error = SdcpHandler::new(address).bind(echo_handler).await;
+    match error {
+        Err(ConnError::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse),
+        _ => panic!("Binding must fail"),
+    }
+}
+
+#[tokio::test]
+async fn test_bind_retries_transient_errors() {
+    let occupied = SdcpHandler::new(local_address(0))
+        .bind(echo_handler)
+        .await
+        .unwrap();
+    let address = occupied.local_address();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        occupied.shutdown().await;
+    });
+
+    let mut handler = SdcpHandler::new(address);
+    handler.set_retry_policy(RetryPolicy::new(
+        10,
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+    ));
+    let server = handler.bind(echo_handler).await.unwrap();
+    assert_eq!(server.local_address(), address);
+}
+
+#[tokio::test]
+async fn test_server_errors_channel() {
+    let mut server = SdcpHandler::new(local_address(0))
+        .bind(echo_handler)
+        .await
+        .unwrap();
+    let mut errors = server.take_errors().unwrap();
+
+    let mut stream = TcpStream::connect(server.local_address()).await.unwrap();
+    stream.write_all(b"HTTP 1.1.0\n\0\0\0\0").await.unwrap();
+
+    let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        error,
+        ServerError::Session(_, SessionError::Recv(RecvError::InvalidPacket))
+    ));
+}
+
+#[test]
+fn test_command_round_trip() {
+    for command in [
+        SdcpCommand::Setp,
+        SdcpCommand::Getp,
+        SdcpCommand::Beat,
+        SdcpCommand::Info,
+        SdcpCommand::Disc,
+        SdcpCommand::Subs,
+        SdcpCommand::Unsu,
+        SdcpCommand::Noti,
+        SdcpCommand::None,
+        SdcpCommand::Unknown("RSET".to_string()),
+    ] {
+        assert_eq!(command.to_string().parse::<SdcpCommand>().unwrap(), command);
+    }
+    assert_eq!("getp".parse::<SdcpCommand>().unwrap(), SdcpCommand::Getp);
+    assert_eq!("failed".parse::<SdcpResult>().unwrap(), SdcpResult::Failed);
+}
+
+#[test]
+fn test_param_value_round_trip() {
+    for value in [
+        ParamValue::Bool(true),
+        ParamValue::Bool(false),
+        ParamValue::Integer(-42),
+        ParamValue::Float(10.25),
+        ParamValue::Float(10.0),
+        ParamValue::String("KITCHEN".to_string()),
+        ParamValue::String("".to_string()),
+    ] {
+        assert_eq!(value.to_string().parse::<ParamValue>().unwrap(), value);
+    }
+    assert_eq!(
+        "TRUE".parse::<ParamValue>().unwrap(),
+        ParamValue::Bool(true)
+    );
+    assert_eq!(
+        "INF".parse::<ParamValue>().unwrap(),
+        ParamValue::from("INF")
+    );
+    for value in ["true", "12", "-1.5", "\"12\"", "\"", "KITCHEN"] {
+        let value = ParamValue::from(value);
+        assert_eq!(ParamValue::from_text(&value.to_text()), value);
+    }
+    assert_eq!(ParamValue::from("12").to_text(), "\"12\"");
+    assert_eq!(ParamValue::from_text("12"), ParamValue::Integer(12));
+}
+
+#[test]
+fn test_frame_keeps_parameter_types() {
+    let packet = make_packet(SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: Some(7),
+        command: SdcpCommand::Setp,
+        parameters: vec![
+            ParamItem::new("STATUS".to_string(), ParamValue::Bool(true)),
+            ParamItem::new("PWRCON".to_string(), ParamValue::Integer(1500)),
+            ParamItem::new("VOLTAGE".to_string(), ParamValue::Float(229.5)),
+        ],
+        result: SdcpResult::Ok,
+    });
+    let frame = make_frame(packet).unwrap();
+    assert_eq!(frame.id, Some(7));
+    assert_eq!(frame.command, SdcpCommand::Setp);
+    assert_eq!(frame.parameters[0].value, ParamValue::Bool(true));
+    assert_eq!(frame.parameters[1].value, ParamValue::Integer(1500));
+    assert_eq!(frame.parameters[2].value, ParamValue::Float(229.5));
+}
+
+#[test]
+fn test_frame_escapes_separators() {
+    let packet = make_packet(SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::Setp,
+        parameters: vec![ParamItem::new(
+            "NAME".to_string(),
+            ParamValue::from("Kitchen; left=1 \\ 2"),
+        )],
+        result: SdcpResult::Ok,
+    });
+    assert_eq!(
+        packet,
+        "Command=SETP;Result=OK;NAME=Kitchen\\; left\\=1 \\\\ 2;"
+    );
+
+    let frame = make_frame_with_case(packet.clone(), ValueCase::Preserve).unwrap();
+    assert_eq!(
+        frame.parameters[0].value,
+        ParamValue::from("Kitchen; left=1 \\ 2")
+    );
+    let frame = make_frame(packet).unwrap();
+    assert_eq!(
+        frame.parameters[0].value,
+        ParamValue::from("KITCHEN; LEFT=1 \\ 2")
+    );
+    assert!(make_frame("Command=GETP;STATUS=\\".to_string()).is_err());
+
+    let frame = make_frame("Command=SETP;URL=a=b;".to_string()).unwrap();
+    assert_eq!(frame.parameters[0].value, ParamValue::from("A=B"));
+    assert!(make_frame("Command=SETP;URL;".to_string()).is_err());
+}
+
+#[test]
+fn test_frame_keeps_parameters_named_like_keys() {
+    let packet = make_packet(SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: Some(7),
+        command: SdcpCommand::Setp,
+        parameters: vec![
+            ParamItem::new("ID".to_string(), ParamValue::from("A1")),
+            ParamItem::new("RESULT".to_string(), ParamValue::from("FAILED")),
+        ],
+        result: SdcpResult::Ok,
+    });
+    let frame = make_frame(packet).unwrap();
+    assert_eq!(frame.id, Some(7));
+    assert_eq!(frame.result, SdcpResult::Ok);
+    assert_eq!(frame.parameters[0].name, "ID");
+    assert_eq!(frame.parameters[0].value, ParamValue::from("A1"));
+    assert_eq!(frame.parameters[1].name, "RESULT");
+}
+
+proptest! {
+    #[test]
+    fn test_escape_round_trip(value in any::<String>()) {
+        prop_assert_eq!(unescape(&escape(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_frame_round_trip(
+        parameters in proptest::collection::vec(
+            (any::<String>(), any::<String>()),
+            1..8,
+        )
+    ) {
+        let packet = make_packet(SdcpFrame {
+            protocol: SDCP_PACKET_HEADER.to_string(),
+            id: None,
+            command: SdcpCommand::Setp,
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| ParamItem::new(name.clone(), ParamValue::from(value.as_str())))
+                .collect(),
+            result: SdcpResult::Ok,
+        });
+        let frame = make_frame_with_case(packet, ValueCase::Preserve).unwrap();
+        prop_assert_eq!(frame.parameters.len(), parameters.len());
+        for (item, (name, value)) in frame.parameters.iter().zip(parameters.iter()) {
+            prop_assert_eq!(&item.name, &name.to_uppercase());
+            prop_assert_eq!(&item.value, &ParamValue::from(value.as_str()));
+        }
+    }
+}
+
+// Accepts connections and keeps them open without ever answering
+async fn start_silent_server() -> (SocketAddr, Arc<Mutex<usize>>) {
+    let listener = TcpListener::bind(local_address(0)).await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let connections = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&connections);
+    tokio::spawn(async move {
+        let mut streams = vec![];
+        while let Ok((stream, _)) = listener.accept().await {
+            *counter.lock().unwrap() += 1;
+            streams.push(stream);
+        }
+    });
+    (address, connections)
+}
+
+fn silent_client(retries: u32) -> SdcpHandler {
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_timeouts(Some(Duration::from_millis(100)), None);
+    handler.set_request_retry_policy(RetryPolicy::new(
+        retries,
+        Duration::from_millis(10),
+        Duration::from_millis(10),
+    ));
+    handler
+}
+
+#[tokio::test]
+async fn test_request_timeout() {
+    let (address, _) = start_silent_server().await;
+    let handler = silent_client(0);
+    let result = tokio::time::timeout(
+        Duration::from_secs(2),
+        handler.request(getp_frame("STATUS"), address),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(result, Err(RequestError::Timeout)));
+}
+
+#[tokio::test]
+async fn test_request_retries_idempotent_commands_only() {
+    let (address, connections) = start_silent_server().await;
+    let handler = silent_client(2);
+    let result = handler.request(getp_frame("STATUS"), address).await;
+    assert!(matches!(result, Err(RequestError::Timeout)));
+    assert_eq!(*connections.lock().unwrap(), 3);
+
+    let (address, connections) = start_silent_server().await;
+    let frame = SdcpFrame {
+        command: SdcpCommand::Setp,
+        ..getp_frame("STATUS")
+    };
+    let result = handler.request(frame, address).await;
+    assert!(matches!(result, Err(RequestError::Timeout)));
+    assert_eq!(*connections.lock().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_session_request_timeout() {
+    let (address, _) = start_silent_server().await;
+    let options = SessionOptions {
+        timeouts: SessionTimeouts {
+            request: Some(Duration::from_millis(100)),
+            ..SessionTimeouts::default()
+        },
+        ..SessionOptions::default()
+    };
+    // A silent peer would never answer the handshake either, so it is skipped here
+    let stream = TcpStream::connect(address).await.unwrap();
+    let session = SdcpSession::from_stream(stream, options);
+    let result = session.request(getp_frame("STATUS")).await;
+    assert!(matches!(result, Err(RequestError::Timeout)));
+    assert!(!session.is_closed());
+}
+
+#[tokio::test]
+async fn test_session_unreadable_request_gets_its_reply() {
+    let address = start_server().await;
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    // GETP without parameters cannot be read by the server, its error reply keeps the id
+    let mut frame = getp_frame("STATUS");
+    frame.parameters.clear();
+    let result = tokio::time::timeout(Duration::from_secs(1), session.request(frame))
+        .await
+        .unwrap();
+    assert!(matches!(result, Err(RequestError::Device { .. })));
+    assert!(session.request(getp_frame("STATUS")).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_session_unreadable_reply_fails_pending_requests() {
+    let listener = TcpListener::bind(local_address(0)).await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = [0; 256];
+        let _ = stream.read(&mut buffer).await.unwrap();
+        let mut reply = BytesMut::new();
+        SdcpCodec::default().encode("Command;", &mut reply).unwrap();
+        stream.write_all(&reply).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    });
+
+    let stream = TcpStream::connect(address).await.unwrap();
+    let session = SdcpSession::from_stream(stream, SessionOptions::default());
+    let result = tokio::time::timeout(
+        Duration::from_secs(1),
+        session.request(getp_frame("STATUS")),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(result, Err(RequestError::InvalidPacket)));
+    assert!(!session.is_closed());
+}
+
+#[test]
+fn test_version_compatibility() {
+    assert_eq!(
+        "0.1.0".parse::<SdcpVersion>().unwrap(),
+        SdcpVersion::new(0, 1, 0)
+    );
+    assert!("0.1".parse::<SdcpVersion>().is_err());
+    assert!("0.1.x".parse::<SdcpVersion>().is_err());
+    assert_eq!(SDCP_BASE_VERSION.header(), SDCP_PACKET_HEADER);
+
+    let newer = SdcpVersion::new(0, 3, 2);
+    assert_eq!(newer.negotiate(&SDCP_VERSION), Some(SDCP_VERSION));
+    assert_eq!(SDCP_VERSION.negotiate(&newer), Some(SDCP_VERSION));
+    assert_eq!(SDCP_VERSION.negotiate(&SdcpVersion::new(1, 0, 0)), None);
+}
+
+#[test]
+fn test_codec_accepts_compatible_versions_only() {
+    let mut codec = SdcpCodec::default();
+    let mut newer = SdcpCodec::default();
+    newer.set_version(SdcpVersion::new(0, 12, 3));
+    let mut buffer = BytesMut::new();
+    newer.encode("Command=BEAT;", &mut buffer).unwrap();
+    assert!(buffer.starts_with(b"SDCP 0.12.3\n"));
+    assert_eq!(codec.decode(&mut buffer).unwrap().unwrap(), "Command=BEAT;");
+
+    let mut major = SdcpCodec::default();
+    major.set_version(SdcpVersion::new(1, 0, 0));
+    major.encode("Command=BEAT;", &mut buffer).unwrap();
+    assert!(matches!(
+        codec.decode(&mut buffer),
+        Err(RecvError::UnsupportedVersion(version)) if version == SdcpVersion::new(1, 0, 0)
+    ));
+}
+
+#[test]
+fn test_codec_keeps_baseline_layout() {
+    let baseline = b"SDCP 0.0.1\0\0\0\x0dCommand=BEAT;";
+    let mut codec = SdcpCodec::default();
+    let mut buffer = BytesMut::from(&baseline[..]);
+    assert_eq!(codec.decode(&mut buffer).unwrap().unwrap(), "Command=BEAT;");
+    assert!(buffer.is_empty());
+
+    codec.set_version(SDCP_BASE_VERSION);
+    codec.encode("Command=BEAT;", &mut buffer).unwrap();
+    assert_eq!(&buffer[..], &baseline[..]);
+}
+
+#[test]
+fn test_frame_codec_reports_header_version() {
+    let mut newer = SdcpFrameCodec::default();
+    newer.set_version(SdcpVersion::new(0, 12, 3));
+    let mut buffer = BytesMut::new();
+    newer.encode(typed_frame(), &mut buffer).unwrap();
+    let frame = SdcpFrameCodec::default()
+        .decode(&mut buffer)
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(frame.protocol, "SDCP 0.12.3");
+
+    // Only the delimited header of newer versions carries a length whose first byte is not zero
+    let mut codec = SdcpCodec::new(32 * 1024 * 1024);
+    let data = "A".repeat(0x0100_0000);
+    assert!(matches!(
+        codec.encode(data.as_str(), &mut buffer),
+        Err(SendError::FrameTooLarge(_))
+    ));
+    codec.set_version(SDCP_VERSION);
+    codec.encode(data.as_str(), &mut buffer).unwrap();
+    assert_eq!(
+        codec.decode(&mut buffer).unwrap().unwrap().len(),
+        data.len()
+    );
+
+    let mut buffer = BytesMut::from(&b"SDCP 0.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0"[..]);
+    assert!(matches!(
+        codec.decode(&mut buffer),
+        Err(RecvError::InvalidPacket)
+    ));
+}
+
+#[tokio::test]
+async fn test_session_negotiates_version() {
+    let address = start_server().await;
+    let session = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(session.version(), SDCP_VERSION);
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_version(SDCP_BASE_VERSION);
+    let server = handler.bind(echo_handler).await.unwrap();
+    let session = SdcpSession::connect(server.local_address(), SessionOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(session.version(), SDCP_BASE_VERSION);
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+}
+
+#[tokio::test]
+async fn test_session_rejects_unsupported_version() {
+    let address = start_server().await;
+    let options = SessionOptions {
+        version: SdcpVersion::new(1, 0, 0),
+        ..SessionOptions::default()
+    };
+    let result = SdcpSession::connect(address, options).await;
+    assert!(matches!(
+        result,
+        Err(ConnError::UnsupportedVersion(version)) if version == SDCP_VERSION
+    ));
+}
+
+fn typed_frame() -> SdcpFrame {
+    SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: Some(7),
+        command: SdcpCommand::Setp,
+        parameters: vec![
+            ParamItem::new("STATUS".to_string(), ParamValue::Bool(true)),
+            ParamItem::new("LIMIT".to_string(), ParamValue::Integer(-3)),
+            ParamItem::new("PWRCON".to_string(), ParamValue::Float(2.0)),
+            ParamItem::new("NAME".to_string(), ParamValue::from("true")),
+        ],
+        result: SdcpResult::Ok,
+    }
+}
+
+#[test]
+fn test_binary_frame_round_trip() {
+    let packet = make_binary_packet(typed_frame());
+    let frame = make_binary_frame(&packet, ValueCase::Preserve).unwrap();
+    let expected = typed_frame();
+    assert_eq!(frame.id, expected.id);
+    assert_eq!(frame.command, expected.command);
+    assert_eq!(frame.result, expected.result);
+    for (item, expected) in frame.parameters.iter().zip(expected.parameters.iter()) {
+        assert_eq!(item.name, expected.name);
+        assert_eq!(item.value, expected.value);
+    }
+
+    assert!(make_binary_frame(&packet[..packet.len() - 1], ValueCase::Preserve).is_err());
+}
+
+#[tokio::test]
+async fn test_session_binary_encoding() {
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_encoding(PayloadEncoding::Binary);
+    let server = handler.bind(echo_handler).await.unwrap();
+    let address = server.local_address();
+    let options = SessionOptions {
+        encoding: PayloadEncoding::Binary,
+        value_case: ValueCase::Preserve,
+        ..SessionOptions::default()
+    };
+    let session = SdcpSession::connect(address, options).await.unwrap();
+    assert_eq!(session.encoding(), PayloadEncoding::Binary);
+
+    let response = session.request(typed_frame()).await.unwrap();
+    assert_eq!(response.command, SdcpCommand::Setp);
+    assert_eq!(response.parameters.len(), 4);
+    assert_eq!(response.parameters[2].value, ParamValue::Float(2.0));
+    assert_eq!(response.parameters[3].value, ParamValue::from("TRUE"));
+
+    // A binary server still starts every connection in text, so plain clients get through
+    let text = SdcpSession::connect(address, SessionOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(text.encoding(), PayloadEncoding::Text);
+    let response = text.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+
+    let mut client = SdcpHandler::new(local_address(0));
+    client.set_encoding(PayloadEncoding::Binary);
+    client.set_value_case(ValueCase::Preserve);
+    let response = client.request(typed_frame(), address).await.unwrap();
+    assert_eq!(response.parameters[3].value, ParamValue::from("TRUE"));
+}
+
+#[tokio::test]
+async fn test_text_server_refuses_binary_encoding() {
+    let address = start_server().await;
+    let options = SessionOptions {
+        encoding: PayloadEncoding::Binary,
+        ..SessionOptions::default()
+    };
+    let session = SdcpSession::connect(address, options).await.unwrap();
+    assert_eq!(session.encoding(), PayloadEncoding::Text);
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+}
+
+// Writes a test CA with a server and a client certificate signed by it into a temporary
+// directory, which is removed once the returned TempDir is dropped
+#[cfg(feature = "tls")]
+fn write_test_pki() -> TempDir {
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+
+    let pki = TempDir::new().unwrap();
+    let directory = pki.path();
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    std::fs::write(directory.join("ca.pem"), ca.pem()).unwrap();
+
+    for peer in ["server", "client"] {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        std::fs::write(directory.join(format!("{}.pem", peer)), cert.pem()).unwrap();
+        std::fs::write(directory.join(format!("{}.key", peer)), key.serialize_pem()).unwrap();
+    }
+    pki
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn test_tls_request_and_session() {
+    let pki = write_test_pki();
+    let pki = pki.path();
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler
+        .set_tls_server(TlsServer::new(&pki.join("server.pem"), &pki.join("server.key")).unwrap());
+    let server = handler.bind(echo_handler).await.unwrap();
+    let address = server.local_address();
+
+    let tls = TlsClient::new(&pki.join("ca.pem"), "localhost").unwrap();
+    let mut client = SdcpHandler::new(local_address(0));
+    client.set_tls_client(tls.clone());
+    let response = client.request(getp_frame("STATUS"), address).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+
+    let session = SdcpSession::connect_tls(address, SessionOptions::default(), &tls)
+        .await
+        .unwrap();
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+
+    let plain = SdcpHandler::new(local_address(0));
+    assert!(plain.request(getp_frame("STATUS"), address).await.is_err());
+}
+
+#[cfg(feature = "tls")]
+#[tokio::test]
+async fn test_tls_client_authentication() {
+    let pki = write_test_pki();
+    let pki = pki.path();
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_tls_server(
+        TlsServer::with_client_auth(
+            &pki.join("server.pem"),
+            &pki.join("server.key"),
+            &pki.join("ca.pem"),
+        )
+        .unwrap(),
+    );
+    let mut server = handler.bind(echo_handler).await.unwrap();
+    let address = server.local_address();
+    let mut errors = server.take_errors().unwrap();
+
+    let anonymous = TlsClient::new(&pki.join("ca.pem"), "localhost").unwrap();
+    let result = SdcpSession::connect_tls(address, SessionOptions::default(), &anonymous).await;
+    assert!(result.is_err());
+    let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(error, ServerError::Tls(_, _)));
+
+    let enrolled = TlsClient::with_identity(
+        &pki.join("ca.pem"),
+        "localhost",
+        &pki.join("client.pem"),
+        &pki.join("client.key"),
+    )
+    .unwrap();
+    let session = SdcpSession::connect_tls(address, SessionOptions::default(), &enrolled)
+        .await
+        .unwrap();
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+}
+
+fn shared_frame() -> SdcpFrameShared {
+    Arc::new(Mutex::new(Box::new(make_telemetry_frame(vec![]))))
+}
+
+fn temperature_frame(value: f64) -> SdcpFrame {
+    make_telemetry_frame(vec![ParamItem::new(
+        "TEMPERATURE".to_string(),
+        ParamValue::Float(value),
+    )])
+}
+
+fn temperature_datagram(value: f64) -> Vec<u8> {
+    make_datagram(temperature_frame(value), &mut SdcpFrameCodec::default()).unwrap()
+}
+
+#[test]
+fn test_datagram_round_trip() {
+    for encoding in [PayloadEncoding::Text, PayloadEncoding::Binary] {
+        let mut codec = SdcpFrameCodec::default();
+        codec.set_encoding(encoding);
+        let datagram = make_datagram(typed_frame(), &mut codec).unwrap();
+        let (frame, length) = read_datagram(&datagram, &mut codec).unwrap();
+        assert_eq!(length, datagram.len());
+        assert_eq!(frame.parameters.len(), typed_frame().parameters.len());
+        assert!(read_datagram(&datagram[..length - 1], &mut codec).is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_udp_handler_receives_frames() {
+    let frame = shared_frame();
+    let handler = SdcpuHandler::new(
+        local_address(0),
+        Arc::clone(&frame),
+        SdcpuOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let socket = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    socket
+        .send_to(&temperature_datagram(21.5), handler.local_address())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let frame = frame.lock().unwrap();
+    assert_eq!(frame.command, SdcpCommand::None);
+    assert_eq!(frame.parameters[0].name, "TEMPERATURE");
+    assert_eq!(frame.parameters[0].value, ParamValue::Float(21.5));
+}
+
+#[tokio::test]
+async fn test_udp_handler_reports_address_in_use() {
+    let handler = SdcpuHandler::new(local_address(0), shared_frame(), SdcpuOptions::default())
+        .await
+        .unwrap();
+    let error = SdcpuHandler::new(
+        handler.local_address(),
+        shared_frame(),
+        SdcpuOptions::default(),
+    )
+    .await;
+    match error {
+        Err(ConnError::Io(error)) => assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse),
+        _ => panic!("Binding must fail"),
+    }
+}
+
+#[tokio::test]
+async fn test_udp_handler_reports_malformed_datagrams() {
+    let mut handler = SdcpuHandler::new(local_address(0), shared_frame(), SdcpuOptions::default())
+        .await
+        .unwrap();
+    let mut errors = handler.take_errors().unwrap();
+
+    let socket = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    socket
+        .send_to(&[0xff, 0xfe, 0xfd], handler.local_address())
+        .await
+        .unwrap();
+
+    let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(error, FrameError::Recv(RecvError::InvalidPacket)));
+}
+
+#[tokio::test]
+async fn test_udp_handler_rejects_unauthentic_datagrams() {
+    let mut auth = SdcpuAuth::default();
+    auth.add_key("th-1", b"secret".to_vec());
+    let options = SdcpuOptions {
+        auth: Some(auth),
+        ..SdcpuOptions::default()
+    };
+    let frame = shared_frame();
+    let mut handler = SdcpuHandler::new(local_address(0), Arc::clone(&frame), options)
+        .await
+        .unwrap();
+    let mut errors = handler.take_errors().unwrap();
+    let socket = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    let send = |datagram: Vec<u8>| {
+        let socket = &socket;
+        let address = handler.local_address();
+        async move {
+            socket.send_to(&datagram, address).await.unwrap();
+        }
+    };
+    let temperature = |frame: &SdcpFrameShared| {
+        let frame = frame.lock().unwrap();
+        frame
+            .parameters
+            .iter()
+            .find(|item| item.name == "TEMPERATURE")
+            .map(|item| item.value.clone())
+    };
+
+    let mut signer = SdcpuSigner::new("th-1".to_string(), b"secret".to_vec());
+    let signed = signer.sign(&temperature_frame(21.5)).unwrap();
+    send(signed.clone()).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(temperature(&frame), Some(ParamValue::Float(21.5)));
+
+    // Turn the last digit of 21.5 into 21.6, the frame stays well formed
+    let mut tampered = signed.clone();
+    tampered[signed.len() - 34] += 1;
+    send(signed).await;
+    send(tampered).await;
+    send(temperature_datagram(99.0)).await;
+    send(
+        SdcpuSigner::new("th-2".to_string(), b"secret".to_vec())
+            .sign(&temperature_frame(99.0))
+            .unwrap(),
+    )
+    .await;
+    send(
+        SdcpuSigner::new("th-1".to_string(), b"guess".to_vec())
+            .sign(&temperature_frame(99.0))
+            .unwrap(),
+    )
+    .await;
+
+    for _ in 0..5 {
+        let error = tokio::time::timeout(Duration::from_secs(1), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(error, FrameError::Auth(_)));
+    }
+    assert_eq!(
+        handler.rejected(),
+        RejectedStats {
+            unsigned: 1,
+            unknown_device: 1,
+            bad_signature: 2,
+            replayed: 1,
+        }
+    );
+    assert_eq!(temperature(&frame), Some(ParamValue::Float(21.5)));
+}
+
+#[tokio::test]
+async fn test_udp_handler_keeps_state_per_sender() {
+    let handler = SdcpuHandler::new(local_address(0), shared_frame(), SdcpuOptions::default())
+        .await
+        .unwrap();
+    let mut subscription = handler.subscribe(DeviceKey::device("th-2"));
+    assert!(subscription.borrow().is_none());
+
+    let first = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    let second = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    let mut named = temperature_frame(18.0);
+    named.parameters.insert(
+        0,
+        ParamItem::new("DEVICE".to_string(), ParamValue::from("th-2")),
+    );
+    first
+        .send_to(&temperature_datagram(21.5), handler.local_address())
+        .await
+        .unwrap();
+    second
+        .send_to(
+            &make_datagram(named, &mut SdcpFrameCodec::default()).unwrap(),
+            handler.local_address(),
+        )
+        .await
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), subscription.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    let device = subscription.borrow().clone().unwrap();
+    assert_eq!(device.address, second.local_addr().unwrap());
+    assert_eq!(device.frame.parameters[1].value, ParamValue::Float(18.0));
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(handler.devices().len(), 2);
+    let device = handler
+        .device(&DeviceKey::Address(first.local_addr().unwrap()))
+        .unwrap();
+    assert_eq!(device.frame.parameters[0].value, ParamValue::Float(21.5));
+    assert!(device.last_seen.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_udp_handler_streams_frames_until_shutdown() {
+    let handler = SdcpuHandler::new(local_address(0), shared_frame(), SdcpuOptions::default())
+        .await
+        .unwrap();
+    let mut frames = handler.frames();
+
+    let socket = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    for value in [20.0, 20.5] {
+        socket
+            .send_to(&temperature_datagram(value), handler.local_address())
+            .await
+            .unwrap();
+    }
+    for value in [20.0, 20.5] {
+        let device = tokio::time::timeout(Duration::from_secs(1), frames.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(device.address, socket.local_addr().unwrap());
+        assert_eq!(device.frame.parameters[0].value, ParamValue::Float(value));
+    }
+
+    tokio::time::timeout(Duration::from_secs(1), handler.shutdown())
+        .await
+        .unwrap();
+    let end = tokio::time::timeout(Duration::from_secs(1), frames.next())
+        .await
+        .unwrap();
+    assert!(end.is_none());
+}
+
+#[tokio::test]
+async fn test_udp_handler_flags_stale_and_invalid_devices() {
+    let options = SdcpuOptions {
+        stale_after: Duration::from_millis(200),
+        ..SdcpuOptions::default()
+    };
+    let frame = shared_frame();
+    let handler = SdcpuHandler::new(local_address(0), Arc::clone(&frame), options)
+        .await
+        .unwrap();
+    let socket = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    let key = DeviceKey::Address(socket.local_addr().unwrap());
+    let mut subscription = handler.subscribe(key.clone());
+
+    socket
+        .send_to(&temperature_datagram(21.5), handler.local_address())
+        .await
+        .unwrap();
+    subscription.changed().await.unwrap();
+    assert_eq!(handler.device(&key).unwrap().status, DeviceStatus::Online);
+
+    socket
+        .send_to(&[0xff, 0xfe, 0xfd], handler.local_address())
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(1), subscription.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    let device = handler.device(&key).unwrap();
+    assert_eq!(device.status, DeviceStatus::Invalid);
+    assert_eq!(device.frame.parameters[0].value, ParamValue::Float(21.5));
+    assert_eq!(
+        frame.lock().unwrap().parameters[0].value,
+        ParamValue::Float(21.5)
+    );
+
+    tokio::time::timeout(Duration::from_secs(1), subscription.changed())
+        .await
+        .unwrap()
+        .unwrap();
+    let device = subscription.borrow().clone().unwrap();
+    assert_eq!(device.status, DeviceStatus::Stale);
+    assert!(device.age() >= Duration::from_millis(200));
+    assert_eq!(device.frame.parameters[0].value, ParamValue::Float(21.5));
+}
+
+#[tokio::test]
+async fn test_udp_handler_streams_status_changes() {
+    let options = SdcpuOptions {
+        stale_after: Duration::from_millis(200),
+        ..SdcpuOptions::default()
+    };
+    let handler = SdcpuHandler::new(local_address(0), shared_frame(), options)
+        .await
+        .unwrap();
+    let mut frames = handler.frames();
+    let socket = tokio::net::UdpSocket::bind(local_address(0)).await.unwrap();
+    socket
+        .send_to(&temperature_datagram(21.5), handler.local_address())
+        .await
+        .unwrap();
+    socket
+        .send_to(&[0xff, 0xfe, 0xfd], handler.local_address())
+        .await
+        .unwrap();
+
+    for status in [
+        DeviceStatus::Online,
+        DeviceStatus::Invalid,
+        DeviceStatus::Stale,
+    ] {
+        let device = tokio::time::timeout(Duration::from_secs(1), frames.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(device.status, status);
+        assert_eq!(device.frame.parameters[0].value, ParamValue::Float(21.5));
+    }
+}
+
+fn device_info(device: &str, device_type: &str, port: u16) -> DeviceInfo {
+    DeviceInfo::new(
+        device.to_string(),
+        device_type.to_string(),
+        local_address(port),
+        vec!["STATUS".to_string(), "PWRCON".to_string()],
+    )
+}
+
+#[tokio::test]
+async fn test_discovery_finds_devices_on_loopback() {
+    // The second responder shares the port the first one was given
+    let mut shared = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+    let mut responders = vec![];
+    for (device, device_type, port) in [("ES-1", "SOCKET", 55101), ("ES-2", "SOCKET", 55102)] {
+        let responder = DiscoveryResponder::bind(shared, device_info(device, device_type, port))
+            .await
+            .unwrap();
+        shared = responder.local_address();
+        responders.push(responder);
+    }
+    let thermometer =
+        DiscoveryResponder::bind(local_address(0), device_info("TH-1", "THERMOMETER", 4000))
+            .await
+            .unwrap();
+
+    let broadcast = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 255, 255, 255)), shared.port());
+    let targets = [broadcast, thermometer.local_address()];
+    let devices = discover(&targets, None, Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert_eq!(
+        devices,
+        vec![
+            device_info("ES-1", "SOCKET", 55101),
+            device_info("ES-2", "SOCKET", 55102),
+            device_info("TH-1", "THERMOMETER", 4000),
+        ]
+    );
+    assert!(devices[0].has_capability("pwrcon"));
+
+    let devices = discover(&targets, Some("thermometer"), Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert_eq!(devices, vec![device_info("TH-1", "THERMOMETER", 4000)]);
+
+    for responder in responders {
+        responder.shutdown().await;
+    }
+    thermometer.shutdown().await;
+}
+
+// The loopback interface is joined as well, so no multicast route is needed here
+#[tokio::test]
+async fn test_discovery_finds_devices_over_multicast() {
+    let group = SocketAddr::new(IpAddr::V4(SDCP_DISCOVERY_GROUP), 0);
+    let grouped = DiscoveryResponder::bind(group, device_info("TH-2", "GROUPED", 4001))
+        .await
+        .unwrap();
+    let group = SocketAddr::new(
+        IpAddr::V4(SDCP_DISCOVERY_GROUP),
+        grouped.local_address().port(),
+    );
+    // A device bound to the discovery port joins the discovery group on its own
+    let port = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), SDCP_DISCOVERY_PORT);
+    let listening = DiscoveryResponder::bind(port, device_info("ES-3", "LISTENING", 55103))
+        .await
+        .unwrap();
+
+    let devices = discover(&[group], Some("grouped"), Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert_eq!(devices, vec![device_info("TH-2", "GROUPED", 4001)]);
+
+    let target = SocketAddr::new(IpAddr::V4(SDCP_DISCOVERY_GROUP), SDCP_DISCOVERY_PORT);
+    let devices = discover(&[target], Some("listening"), Duration::from_millis(300))
+        .await
+        .unwrap();
+    assert_eq!(devices, vec![device_info("ES-3", "LISTENING", 55103)]);
+
+    grouped.shutdown().await;
+    listening.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_session_notifications() {
+    let notifier = SdcpNotifier::new();
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_notifier(notifier.clone());
+    let server = handler.bind(echo_handler).await.unwrap();
+    let session = SdcpSession::connect(server.local_address(), SessionOptions::default())
+        .await
+        .unwrap();
+    let mut notifications = session.notifications();
+
+    let response = session.subscribe(&["status"]).await.unwrap();
+    assert_eq!(response.command, SdcpCommand::Subs);
+    assert_eq!(response.result, SdcpResult::Ok);
+    assert!(notifier.notify("PWRCON", ParamValue::Integer(100)));
+    assert!(notifier.notify("STATUS", ParamValue::Bool(true)));
+    assert!(!notifier.notify("STATUS", ParamValue::Bool(true)));
+    assert!(notifier.notify("STATUS", ParamValue::Bool(false)));
+    for value in [true, false] {
+        let item = tokio::time::timeout(Duration::from_secs(1), notifications.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.name, "STATUS");
+        assert_eq!(item.value, ParamValue::Bool(value));
+    }
+
+    session.unsubscribe(&[]).await.unwrap();
+    notifier.notify("STATUS", ParamValue::Bool(true));
+    let response = session.request(getp_frame("STATUS")).await.unwrap();
+    assert_eq!(response.result, SdcpResult::Ok);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), notifications.next())
+            .await
+            .is_err()
+    );
+
+    let plain = start_server().await;
+    let session = SdcpSession::connect(plain, SessionOptions::default())
+        .await
+        .unwrap();
+    let error = session.subscribe(&["STATUS"]).await.unwrap_err();
+    assert!(matches!(
+        error,
+        RequestError::Device {
+            code: SdcpErrorCode::UnsupportedCommand,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_batch_requests_run_concurrently() {
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most_in_flight = Arc::new(AtomicUsize::new(0));
+    let slow_handler = {
+        let in_flight = Arc::clone(&in_flight);
+        let most_in_flight = Arc::clone(&most_in_flight);
+        move |frame: FrameResult| {
+            let in_flight = Arc::clone(&in_flight);
+            let most_in_flight = Arc::clone(&most_in_flight);
+            async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most_in_flight.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                echo_handler(frame).await
+            }
+        }
+    };
+    let mut addresses = vec![];
+    for _ in 0..4 {
+        let server = SdcpHandler::new(local_address(0))
+            .bind(slow_handler.clone())
+            .await
+            .unwrap();
+        addresses.push(server.local_address());
+    }
+    // Nothing listens there once the listener is dropped
+    let unreachable = TcpListener::bind(local_address(0))
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let (hanging, _) = start_silent_server().await;
+    let mut requests: Vec<(SocketAddr, SdcpFrame)> = addresses
+        .iter()
+        .map(|address| (*address, getp_frame("STATUS")))
+        .collect();
+    requests.insert(1, (unreachable, getp_frame("STATUS")));
+    requests.push((hanging, getp_frame("STATUS")));
+
+    let mut handler = SdcpHandler::new(local_address(0));
+    handler.set_batch_concurrency(2);
+    handler.set_batch_deadline(Some(Duration::from_millis(500)));
+    let report: BatchReport =
+        tokio::time::timeout(Duration::from_secs(2), handler.request_batch(requests))
+            .await
+            .unwrap();
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 2);
+
+    assert_eq!(report.items.len(), 6);
+    assert_eq!(report.succeeded(), 4);
+    assert!(!report.is_ok());
+    let failures: Vec<SocketAddr> = report.failures().map(|item| item.address).collect();
+    assert_eq!(failures, vec![unreachable, hanging]);
+    assert!(matches!(report.items[5].result, Err(RequestError::Timeout)));
+    assert_eq!(report.items[0].address, addresses[0]);
+    assert_eq!(report.items[2].address, addresses[1]);
+}
+
+#[tokio::test]
+async fn test_batch_reaches_fleet_in_one_round_trip() {
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most_in_flight = Arc::new(AtomicUsize::new(0));
+    let slow_handler = {
+        let in_flight = Arc::clone(&in_flight);
+        let most_in_flight = Arc::clone(&most_in_flight);
+        move |frame: FrameResult| {
+            let in_flight = Arc::clone(&in_flight);
+            let most_in_flight = Arc::clone(&most_in_flight);
+            async move {
+                let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most_in_flight.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                echo_handler(frame).await
+            }
+        }
+    };
+    let mut servers = vec![];
+    for _ in 0..40 {
+        servers.push(
+            SdcpHandler::new(local_address(0))
+                .bind(slow_handler.clone())
+                .await
+                .unwrap(),
+        );
+    }
+    let requests = servers
+        .iter()
+        .map(|server| (server.local_address(), getp_frame("STATUS")))
+        .collect();
+
+    let started = Instant::now();
+    let report = SdcpHandler::new(local_address(0))
+        .request_batch(requests)
+        .await;
+    assert!(report.is_ok());
+    assert_eq!(report.items.len(), 40);
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 40);
+    assert!(started.elapsed() < Duration::from_millis(600));
+}
+
+#[test]
+fn test_device_error_frame_round_trip() {
+    let mut error = DeviceError::new(SdcpErrorCode::InvalidValue, "bad value");
+    error.add_parameter("status", SdcpErrorCode::InvalidValue);
+    error.add_parameter("color", SdcpErrorCode::UnknownParameter);
+    let packet = make_packet(error.frame(SdcpCommand::Setp));
+    let frame = make_frame(packet).unwrap();
+    assert_eq!(DeviceError::from_frame(&frame), Some(error.clone()));
+    let packet = make_binary_packet(error.frame(SdcpCommand::Setp));
+    let frame = make_binary_frame(&packet, ValueCase::Upper).unwrap();
+    assert_eq!(DeviceError::from_frame(&frame), Some(error));
+
+    let legacy = make_frame("Command=SETP;Result=FAILED;".to_string()).unwrap();
+    let error = DeviceError::from_frame(&legacy).unwrap();
+    assert_eq!(error.code, SdcpErrorCode::Unspecified);
+    assert!(error.parameters.is_empty());
+    assert_eq!(DeviceError::from_frame(&getp_frame("STATUS")), None);
+    assert_eq!(
+        "busy".parse::<SdcpErrorCode>().unwrap(),
+        SdcpErrorCode::Busy
+    );
+}
+
+#[tokio::test]
+async fn test_device_errors_are_typed() {
+    let failing_handler = |frame: FrameResult| async move {
+        let frame = frame.unwrap();
+        let mut error = DeviceError::new(SdcpErrorCode::UnknownParameter, "no such parameter");
+        for item in frame.parameters.iter() {
+            error.add_parameter(&item.name, SdcpErrorCode::UnknownParameter);
+        }
+        error.frame(frame.command)
+    };
+    let server = SdcpHandler::new(local_address(0))
+        .bind(failing_handler)
+        .await
+        .unwrap();
+    let expected = "no such parameter (COLOR: UNKNOWN_PARAMETER)";
+
+    let handler = SdcpHandler::new(local_address(0));
+    match handler
+        .request(getp_frame("COLOR"), server.local_address())
+        .await
+    {
+        Err(RequestError::Device { code, message }) => {
+            assert_eq!(code, SdcpErrorCode::UnknownParameter);
+            assert_eq!(message, expected);
+        }
+        _ => panic!("Request must fail with a device error"),
+    }
+
+    let session = SdcpSession::connect(server.local_address(), SessionOptions::default())
+        .await
+        .unwrap();
+    match session.request(getp_frame("COLOR")).await {
+        Err(RequestError::Device { code, message }) => {
+            assert_eq!(code, SdcpErrorCode::UnknownParameter);
+            assert_eq!(message, expected);
+        }
+        _ => panic!("Request must fail with a device error"),
+    }
+    assert!(!session.is_closed());
+}
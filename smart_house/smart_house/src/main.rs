@@ -1,9 +1,9 @@
 use core::panic;
+use futures::StreamExt;
 use std::io::{self, Write};
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::{Arc, Mutex},
-    thread,
     time::Duration,
 };
 
@@ -12,15 +12,32 @@ use smart_house_lib::clever_house::CleverHouse;
 use smart_house_lib::smart_house::clever_room::clever_device::CleverDevice;
 use smart_house_lib::smart_house::clever_room::CleverRoom;
 use smart_house_lib::smart_house::smart_room::electric_socket::ElectricSocket;
+use smart_house_lib::smart_house::smart_room::smart_device::SmartDevice;
 use smart_house_lib::smart_house::smart_room::thermometer::Thermometer;
 use smart_house_lib::smart_house::smart_room::SmartRoom;
 use smart_house_lib::smart_house::SmartHouse;
 
+use sdcp::results::RequestError;
+use sdcp::retry::RetryPolicy;
+use sdcp::session::{SdcpSession, SessionOptions, SessionTimeouts};
+use sdcp::udp::devices::DeviceStatus;
+use sdcp::udp::discovery::{
+    discover, DeviceInfo, SDCP_DISCOVERY_GROUP, SDCP_DISCOVERY_PORT, SDCP_DISCOVERY_WAIT,
+    SDCP_TYPE_SOCKET, SDCP_TYPE_THERMOMETER,
+};
+use sdcp::udp::{make_telemetry_frame, SdcpuHandler, SdcpuOptions};
 use sdcp::{
-    SdcpHandler, SDCP_COMMANDS, SDCP_FAILED, SDCP_OK, SDCP_PACKET_HEADER, SDCP_PARAM_STATUS,
+    ParamValue, SdcpCommand, SdcpErrorCode, SdcpHandler, SdcpResult, SDCP_PACKET_HEADER,
+    SDCP_PARAM_PWRCON, SDCP_PARAM_STATUS,
 };
-use sdcpu::{ParamItem as SdcpuParamItem, SdcpuFrame, SdcpuHandler, SDCPU_PACKET_HEADER};
-use th_simulator::TH_PARAM_TEMPERATURE;
+
+const UDP_LISTEN_TIME: Duration = Duration::from_secs(200);
+// Thermometer simulators are started with this address as their telemetry target
+const UDP_LISTEN_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 4100);
+const SDCP_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+const SDCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const SDCP_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const SDCP_REQUEST_RETRIES: u32 = 2;
 
 #[tokio::main]
 async fn main() {
@@ -36,26 +53,123 @@ async fn main() {
     }
 }
 
+// Simulators on this host answer the loopback broadcast, devices on the network the group
+async fn discover_devices(device_type: &str) -> Vec<DeviceInfo> {
+    let targets = [
+        SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 255, 255, 255)),
+            SDCP_DISCOVERY_PORT,
+        ),
+        SocketAddr::new(IpAddr::V4(SDCP_DISCOVERY_GROUP), SDCP_DISCOVERY_PORT),
+    ];
+    match discover(&targets, Some(device_type), SDCP_DISCOVERY_WAIT).await {
+        Ok(devices) => {
+            for device in devices.iter() {
+                println!(
+                    "Найдено устройство {} ({}) по адресу {}: {}",
+                    device.device,
+                    device.device_type,
+                    device.address,
+                    device.capabilities.join(", ")
+                );
+            }
+            devices
+        }
+        Err(error) => {
+            println!("Discovery error: {}", error);
+            vec![]
+        }
+    }
+}
+
+fn attached_thermometer(
+    device: &str,
+    address: SocketAddr,
+    handler: &SdcpuHandler,
+) -> Option<Thermometer> {
+    match Thermometer::new(format!("Thermometer {}", device), NetConfig::new(address)) {
+        Ok(mut thermometer) => {
+            thermometer.attach(handler);
+            Some(thermometer)
+        }
+        Err(error) => {
+            println!("Device error: {:?}", error);
+            None
+        }
+    }
+}
+
 async fn udp_smart_devices() {
-    let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 4100);
-    let arc_frame = Arc::new(Mutex::new(Box::new(SdcpuFrame {
-        protocol: SDCPU_PACKET_HEADER.to_string(),
-        parameters: vec![],
-    })));
-
-    SdcpuHandler::new(address, Arc::clone(&arc_frame)).await;
-    for _ in 0..200 {
-        thread::sleep(Duration::from_secs(1));
-
-        let arc_frame = &**arc_frame.lock().unwrap();
-        let error_value = SdcpuParamItem::new(TH_PARAM_TEMPERATURE.to_string(), "?".to_string());
-        let temperature = arc_frame
-            .parameters
-            .iter()
-            .find(|&item| item.name.eq("TEMPERATURE"))
-            .unwrap_or(&error_value);
-        print!("Текущая температура: {}\r", temperature.value);
-        io::stdout().flush().unwrap();
+    // Listening goes on without discovery, a lost probe only delays a thermometer until it reports
+    let thermometers = discover_devices(SDCP_TYPE_THERMOMETER).await;
+    if thermometers.is_empty() {
+        println!("Термометры не найдены, ожидание телеметрии");
+    }
+    let arc_frame = Arc::new(Mutex::new(Box::new(make_telemetry_frame(vec![]))));
+
+    let handler =
+        match SdcpuHandler::new(UDP_LISTEN_ADDRESS, arc_frame, SdcpuOptions::default()).await {
+            Ok(handler) => handler,
+            Err(error) => {
+                println!("Binding error: {}", error);
+                return;
+            }
+        };
+    let mut frames = handler.frames();
+    // Every thermometer takes its readings from the shared receiver by its own address
+    let mut devices: Vec<Thermometer> = thermometers
+        .iter()
+        .filter_map(|thermometer| {
+            attached_thermometer(&thermometer.device, thermometer.address, &handler)
+        })
+        .collect();
+    let readings = async {
+        while let Some(frame) = frames.next().await {
+            let index = match devices
+                .iter()
+                .position(|device| device.net_config().net_address == frame.address)
+            {
+                Some(index) => index,
+                // Senders missed by discovery are added the first time the receiver sees them
+                None => {
+                    match attached_thermometer(&frame.address.to_string(), frame.address, &handler)
+                    {
+                        Some(device) => {
+                            devices.push(device);
+                            devices.len() - 1
+                        }
+                        None => continue,
+                    }
+                }
+            };
+            let device = &mut devices[index];
+            let state = match frame.status {
+                DeviceStatus::Online => match device.refresh().await {
+                    Ok(_) => String::new(),
+                    Err(error) => format!(" ({})", error),
+                },
+                DeviceStatus::Stale => String::from(" (нет данных)"),
+                DeviceStatus::Invalid => String::from(" (ошибка)"),
+            };
+            print!("{}{}            \r", device.info(), state);
+            io::stdout().flush().unwrap();
+        }
+    };
+    let _ = tokio::time::timeout(UDP_LISTEN_TIME, readings).await;
+    handler.shutdown().await;
+}
+
+fn print_response(frame: &SdcpFrame) {
+    println!("Protocol: {};", frame.protocol);
+    println!("Command: {};", frame.command);
+    println!("Result: {};", frame.result);
+    match frame.result {
+        SdcpResult::Ok => {
+            for item in frame.parameters.iter() {
+                println!("Parameter: {}={};", item.name, item.value);
+            }
+        }
+        _ => println!("Invalid command response"),
     }
 }
 
@@ -63,42 +177,126 @@ async fn tcp_smart_devices() {
     println!("Server started");
     let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 55000);
 
-    let handler = SdcpHandler::new(address);
-    let es_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 55100);
+    let mut handler = SdcpHandler::new(address);
+    handler.set_connect_timeout(Some(SDCP_CONNECT_TIMEOUT));
+    handler.set_timeouts(Some(SDCP_REQUEST_TIMEOUT), Some(SDCP_REQUEST_TIMEOUT));
+    handler.set_request_retry_policy(RetryPolicy::new(
+        SDCP_REQUEST_RETRIES,
+        Duration::from_millis(200),
+        Duration::from_secs(1),
+    ));
+    let mut es_addresses: Vec<SocketAddr> = discover_devices(SDCP_TYPE_SOCKET)
+        .await
+        .iter()
+        .map(|device| device.address)
+        .collect();
+    if es_addresses.is_empty() {
+        es_addresses.push(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            55100,
+        ));
+    }
+    let es_address = es_addresses[0];
 
     //let parameters = vec![ParamItem::new( SDCP_PARAM_STATUS.to_string(), "".to_string()), ParamItem::new( SDCP_PARAM_PWRCON.to_string(), "".to_string())];
     let parameters = vec![ParamItem::new(
         SDCP_PARAM_STATUS.to_string(),
-        "true".to_string(),
+        ParamValue::Bool(true),
     )];
 
     let frame: SdcpFrame = SdcpFrame {
         protocol: SDCP_PACKET_HEADER.to_string(),
-        command: SDCP_COMMANDS::SETP.to_string(),
+        id: None,
+        command: SdcpCommand::Setp,
         parameters,
-        result: SDCP_OK.to_string(),
+        result: SdcpResult::Ok,
     };
+    // Only the selected socket is switched on, the others are left as they are
     match handler.request(frame, es_address).await {
         Ok(frame) => {
             println!("Request completed successfully");
-            println!("Protocol: {};", frame.protocol);
-            println!("Command: {};", frame.command);
-            println!("Result: {};", frame.result);
-            match frame.result.as_str() {
-                SDCP_OK => {
-                    for item in frame.parameters.iter() {
-                        println!("Parameter: {}={};", item.name, item.value);
-                    }
-                }
-                SDCP_FAILED => {
-                    println!("Command execution failed")
-                }
-                _ => println!("Invalid command response"),
+            print_response(&frame);
+        }
+        Err(RequestError::Device { code, message }) => {
+            println!("Command execution failed: {} {}", code, message);
+        }
+        Err(error) => {
+            println!("Error: {}", error);
+        }
+    }
+
+    let parameters = vec![
+        ParamItem::new(SDCP_PARAM_STATUS.to_string(), ParamValue::from("")),
+        ParamItem::new(SDCP_PARAM_PWRCON.to_string(), ParamValue::from("")),
+    ];
+    let frame: SdcpFrame = SdcpFrame {
+        protocol: SDCP_PACKET_HEADER.to_string(),
+        id: None,
+        command: SdcpCommand::Getp,
+        parameters,
+        result: SdcpResult::Ok,
+    };
+    // Every socket is read at once, not one after another
+    let requests = es_addresses
+        .iter()
+        .map(|address| (*address, frame.clone()))
+        .collect();
+    let report = handler.request_batch(requests).await;
+    for item in report.items.iter() {
+        match &item.result {
+            Ok(frame) => {
+                println!("Request to {} completed successfully", item.address);
+                print_response(frame);
+            }
+            Err(RequestError::Device { code, message }) => {
+                println!(
+                    "Command execution failed on {}: {} {}",
+                    item.address, code, message
+                );
+            }
+            Err(error) => {
+                println!("Error for {}: {}", item.address, error);
             }
         }
+    }
+    println!(
+        "Read {} of {} sockets",
+        report.succeeded(),
+        report.items.len()
+    );
+
+    let options = SessionOptions {
+        keepalive: Some(SDCP_KEEPALIVE_INTERVAL),
+        timeouts: SessionTimeouts {
+            connect: Some(SDCP_CONNECT_TIMEOUT),
+            request: Some(SDCP_REQUEST_TIMEOUT),
+            write: Some(SDCP_REQUEST_TIMEOUT),
+            ..SessionTimeouts::default()
+        },
+        ..SessionOptions::default()
+    };
+    let session = match SdcpSession::connect(es_address, options).await {
+        Ok(session) => session,
         Err(error) => {
             println!("Error: {}", error);
+            return;
         }
+    };
+    // Manual switching of the socket is reported by the device, no polling needed
+    match session.subscribe(&[SDCP_PARAM_STATUS]).await {
+        Ok(_) => {
+            let mut notifications = session.notifications();
+            tokio::spawn(async move {
+                while let Some(item) = notifications.next().await {
+                    println!("Notification: {}={};", item.name, item.value);
+                }
+            });
+        }
+        Err(RequestError::Device {
+            code: SdcpErrorCode::UnsupportedCommand,
+            ..
+        }) => println!("The device does not support notifications"),
+        Err(error) => println!("Error: {}", error),
     }
 
     loop {
@@ -112,25 +310,27 @@ async fn tcp_smart_devices() {
         if !tokens.is_empty() {
             let mut frame: SdcpFrame = SdcpFrame {
                 protocol: SDCP_PACKET_HEADER.to_string(),
-                command: SDCP_COMMANDS::NONE.to_string(),
+                id: None,
+                command: SdcpCommand::None,
                 parameters: vec![],
-                result: SDCP_OK.to_string(),
+                result: SdcpResult::Ok,
             };
             if "SET".to_uppercase().eq(&tokens[0].to_uppercase()) && tokens.len() == 3 {
-                frame.command = SDCP_COMMANDS::SETP.to_string();
-                frame
-                    .parameters
-                    .push(ParamItem::new(tokens[1].to_string(), tokens[2].to_string()))
+                frame.command = SdcpCommand::Setp;
+                frame.parameters.push(ParamItem::new(
+                    tokens[1].to_string(),
+                    tokens[2].parse().unwrap(),
+                ))
             } else if "GET".to_uppercase().eq(&tokens[0].to_uppercase()) && tokens.len() == 2 {
-                frame.command = SDCP_COMMANDS::GETP.to_string();
+                frame.command = SdcpCommand::Getp;
                 frame
                     .parameters
-                    .push(ParamItem::new(tokens[1].to_string(), "".to_string()))
+                    .push(ParamItem::new(tokens[1].to_string(), ParamValue::from("")))
             } else {
                 println!("Invalid command");
                 continue;
             }
-            match handler.request(frame, es_address).await {
+            match session.request(frame).await {
                 Ok(frame) => {
                     println!("Request completed successfully");
                     println!("Protocol: {};", frame.protocol);
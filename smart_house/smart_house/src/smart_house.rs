@@ -1,3 +1,4 @@
+use futures::future::join_all;
 use std::collections::HashMap;
 use std::fmt::Write;
 
@@ -6,16 +7,38 @@ pub mod errors;
 pub mod smart_room;
 
 use crate::smart_house::errors::SmartHouseErrors;
-use crate::smart_house::smart_room::SmartRoom;
+use crate::smart_house::smart_room::smart_device::SmartDevice;
+use crate::smart_house::smart_room::Room;
 use crate::smart_house::smart_room::SMART_ROOM_NAME_MIN_LENGTH;
 
+use sdcp::results::NetResult;
+
 pub const SMART_HOUSE_NAME_MIN_LENGTH: usize = 8;
 
-pub struct SmartHouse {
+// Each device storage style keeps the report format its house has always had
+pub trait HouseFormat {
+    fn house_header(name: &str) -> String {
+        format!("House {}\n", name)
+    }
+    fn room_header(name: &str) -> String {
+        format!("Room: {}\n", name)
+    }
+    fn device_status(room_name: &str, device_info: &str) -> String {
+        format!("Room {} Device status {}\n", room_name, device_info)
+    }
+}
+
+impl HouseFormat for Box<dyn SmartDevice> {}
+
+// Generic over how rooms store their devices, see Room
+pub struct House<D: SmartDevice> {
     name: String,
-    rooms: HashMap<String, SmartRoom>,
+    rooms: HashMap<String, Room<D>>,
 }
-impl SmartHouse {
+
+pub type SmartHouse = House<Box<dyn SmartDevice>>;
+
+impl<D: SmartDevice + HouseFormat> House<D> {
     pub fn new(name: String) -> Result<Self, SmartHouseErrors> {
         if name.len() < SMART_HOUSE_NAME_MIN_LENGTH {
             return Err(SmartHouseErrors::InvalidHouseName);
@@ -26,7 +49,7 @@ impl SmartHouse {
         })
     }
 
-    pub fn add(&mut self, room: SmartRoom) -> Result<(), SmartHouseErrors> {
+    pub fn add(&mut self, room: Room<D>) -> Result<(), SmartHouseErrors> {
         let room_name = String::from(&room.name);
         if room_name.len() < SMART_ROOM_NAME_MIN_LENGTH {
             return Err(SmartHouseErrors::InvalidRoomName);
@@ -35,15 +58,15 @@ impl SmartHouse {
         Ok(())
     }
 
-    pub fn rem(&mut self, room_name: &str) -> Option<SmartRoom> {
+    pub fn rem(&mut self, room_name: &str) -> Option<Room<D>> {
         self.rooms.remove_entry(room_name).map(|(_, room)| room)
     }
 
-    pub fn get(&self, room_name: &str) -> Option<&SmartRoom> {
+    pub fn get(&self, room_name: &str) -> Option<&Room<D>> {
         self.rooms.get(room_name)
     }
 
-    pub fn get_mut(&mut self, room_name: &str) -> Option<&mut SmartRoom> {
+    pub fn get_mut(&mut self, room_name: &str) -> Option<&mut Room<D>> {
         self.rooms.get_mut(room_name)
     }
 
@@ -62,13 +85,11 @@ impl SmartHouse {
     }
 
     pub fn info(&self) -> String {
-        let mut info = String::new();
-        writeln!(info, "House {}", &self.name).unwrap();
+        let mut info = D::house_header(&self.name);
         let mut rooms: Vec<_> = self.rooms.iter().collect();
         rooms.sort_by(|op1, op2| op1.0.cmp(op2.0));
         for val in rooms {
-            writeln!(info, "Room: {}", val.0).unwrap();
-            write!(info, "{}", val.1.info()).unwrap();
+            write!(info, "{}{}", D::room_header(val.0), val.1.info()).unwrap();
         }
         info
     }
@@ -79,25 +100,30 @@ impl SmartHouse {
         device_name: &str,
     ) -> Result<String, SmartHouseErrors> {
         let room = self.rooms.get(room_name);
-        let mut info = String::new();
         match room {
             Some(room) => {
                 let device = room.devices.get(device_name);
                 match device {
-                    Some(device) => {
-                        writeln!(info, "Room {} Device status {}", room_name, device.info())
-                            .unwrap()
-                    }
-                    None => {
-                        return Err(SmartHouseErrors::DeviceNotFound((
-                            room_name.to_string(),
-                            device_name.to_string(),
-                        )))
-                    }
+                    Some(device) => Ok(D::device_status(room_name, &device.info())),
+                    None => Err(SmartHouseErrors::DeviceNotFound((
+                        room_name.to_string(),
+                        device_name.to_string(),
+                    ))),
                 }
             }
-            None => return Err(SmartHouseErrors::RoomNotFound(room_name.to_string())),
+            None => Err(SmartHouseErrors::RoomNotFound(room_name.to_string())),
         }
-        Ok(info)
+    }
+
+    // Every device of every room is refreshed concurrently, results are ordered by room
+    pub async fn refresh(&mut self) -> Vec<(String, Vec<(String, NetResult)>)> {
+        let mut results = join_all(
+            self.rooms
+                .iter_mut()
+                .map(|(name, room)| async move { (name.clone(), room.refresh().await) }),
+        )
+        .await;
+        results.sort_by(|op1, op2| op1.0.cmp(&op2.0));
+        results
     }
 }
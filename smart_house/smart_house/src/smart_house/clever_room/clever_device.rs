@@ -1,7 +1,49 @@
+use async_trait::async_trait;
+
 use crate::smart_house::smart_room::electric_socket::ElectricSocket;
+use crate::smart_house::smart_room::smart_device::{DeviceParam, SmartDevice};
 use crate::smart_house::smart_room::thermometer::Thermometer;
 
+use sdcp::results::{NetError, NetResult};
+use sdcp::ParamValue;
+
 pub enum CleverDevice {
     ElecticSocket(ElectricSocket),
     Thermometer(Thermometer),
 }
+
+#[async_trait]
+impl SmartDevice for CleverDevice {
+    fn identity(&self) -> &String {
+        match self {
+            CleverDevice::ElecticSocket(electric_socket) => electric_socket.identity(),
+            CleverDevice::Thermometer(thermometer) => thermometer.identity(),
+        }
+    }
+    fn info(&self) -> String {
+        match self {
+            CleverDevice::ElecticSocket(electric_socket) => electric_socket.info(),
+            CleverDevice::Thermometer(thermometer) => thermometer.info(),
+        }
+    }
+    async fn refresh(&mut self) -> NetResult {
+        match self {
+            CleverDevice::ElecticSocket(electric_socket) => electric_socket.refresh().await,
+            CleverDevice::Thermometer(thermometer) => thermometer.refresh().await,
+        }
+    }
+    async fn get_param(&mut self, param: DeviceParam) -> Result<ParamValue, NetError> {
+        match self {
+            CleverDevice::ElecticSocket(electric_socket) => electric_socket.get_param(param).await,
+            CleverDevice::Thermometer(thermometer) => thermometer.get_param(param).await,
+        }
+    }
+    async fn set_param(&mut self, param: DeviceParam, value: ParamValue) -> NetResult {
+        match self {
+            CleverDevice::ElecticSocket(electric_socket) => {
+                electric_socket.set_param(param, value).await
+            }
+            CleverDevice::Thermometer(thermometer) => thermometer.set_param(param, value).await,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use futures::future::join_all;
 use std::collections::HashMap;
 use std::fmt::Write;
 
@@ -8,13 +9,19 @@ pub mod thermometer;
 use crate::smart_house::errors::SmartHouseErrors;
 use crate::smart_house::smart_room::smart_device::{SmartDevice, DEVICE_IDENTITY_MIN_LENGTH};
 
+use sdcp::results::NetResult;
+
 pub const SMART_ROOM_NAME_MIN_LENGTH: usize = 8;
 
-pub struct SmartRoom {
+// One room for both device storage styles: trait objects (SmartRoom) or an enum (CleverRoom)
+pub struct Room<D: SmartDevice> {
     pub name: String,
-    pub devices: HashMap<String, Box<dyn SmartDevice>>,
+    pub devices: HashMap<String, D>,
 }
-impl SmartRoom {
+
+pub type SmartRoom = Room<Box<dyn SmartDevice>>;
+
+impl<D: SmartDevice> Room<D> {
     pub fn new(name: String) -> Result<Self, SmartHouseErrors> {
         if name.len() < SMART_ROOM_NAME_MIN_LENGTH {
             return Err(SmartHouseErrors::InvalidRoomName);
@@ -36,7 +43,7 @@ impl SmartRoom {
         info
     }
 
-    pub fn add(&mut self, device: Box<dyn SmartDevice>) -> Result<(), SmartHouseErrors> {
+    pub fn add(&mut self, device: D) -> Result<(), SmartHouseErrors> {
         let device_name = String::from(device.identity());
         if device_name.len() < DEVICE_IDENTITY_MIN_LENGTH {
             return Err(SmartHouseErrors::InvalidDeviceIdentity);
@@ -45,15 +52,31 @@ impl SmartRoom {
         Ok(())
     }
 
-    pub fn rem(&mut self, device_name: &str) -> Option<Box<dyn SmartDevice>> {
+    pub fn rem(&mut self, device_name: &str) -> Option<D> {
         self.devices.remove_entry(device_name).map(|(_, room)| room)
     }
 
-    pub fn get(&self, device_name: &str) -> Option<&dyn SmartDevice> {
-        self.devices.get(device_name).map(|v| v.as_ref())
+    pub fn get(&self, device_name: &str) -> Option<&D> {
+        self.devices.get(device_name)
+    }
+
+    pub fn get_mut(&mut self, device_name: &str) -> Option<&mut D> {
+        self.devices.get_mut(device_name)
     }
 
     pub fn list(&self) -> Vec<String> {
         self.devices.keys().cloned().collect::<Vec<String>>()
     }
+
+    // Devices are asked at once, a device that fails does not hide the others
+    pub async fn refresh(&mut self) -> Vec<(String, NetResult)> {
+        let mut results = join_all(
+            self.devices
+                .iter_mut()
+                .map(|(name, device)| async move { (name.clone(), device.refresh().await) }),
+        )
+        .await;
+        results.sort_by(|op1, op2| op1.0.cmp(&op2.0));
+        results
+    }
 }
@@ -1,17 +1,45 @@
+use async_trait::async_trait;
+use std::collections::VecDeque;
 use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
 
-use crate::smart_house::smart_room::smart_device::DEVICE_IDENTITY_MIN_LENGTH;
+use crate::smart_house::smart_room::smart_device::{
+    find_param, DeviceParam, DEVICE_IDENTITY_MIN_LENGTH,
+};
 use crate::smart_house::smart_room::SmartDevice;
 use crate::smart_house::SmartHouseErrors;
 
-use sdcp::NetConfig;
+use sdcp::results::{NetError, NetResult};
+use sdcp::{
+    NetConfig, ParamItem, ParamValue, SdcpCommand, SdcpFrame, SdcpHandler, SdcpResult,
+    SDCP_PACKET_HEADER, SDCP_PARAM_PWRCON, SDCP_PARAM_STATUS,
+};
+
+// Oldest readings are dropped first, the energy keeps counting regardless
+pub const ELECTRIC_SOCKET_READINGS_CAPACITY: usize = 1440;
+
+const SECONDS_PER_HOUR: f64 = 3600.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerReading {
+    pub at: Instant,
+    pub power_consumption: u32,
+}
 
 pub struct ElectricSocket {
     name: String,
     power_consumption: u32,
     status: bool,
+    net_config: NetConfig,
+    handler: Arc<SdcpHandler>,
+    readings: VecDeque<PowerReading>,
+    energy: f64,
+    energy_since: Instant,
 }
 
+#[async_trait]
 impl SmartDevice for ElectricSocket {
     fn identity(&self) -> &String {
         &self.name
@@ -29,30 +57,57 @@ impl SmartDevice for ElectricSocket {
         } else {
             write!(info, " State: OFF").unwrap();
         }
+        write!(info, " Energy: {:.3} kWh", self.energy_kwh()).unwrap();
         info
     }
-    fn getp(&self) -> sdcp::results::NetResult {
-        Ok(vec![])
+    async fn refresh(&mut self) -> NetResult {
+        let parameters = vec![
+            ParamItem::new(SDCP_PARAM_STATUS.to_string(), ParamValue::from("")),
+            ParamItem::new(SDCP_PARAM_PWRCON.to_string(), ParamValue::from("")),
+        ];
+        self.request(SdcpCommand::Getp, parameters).await
     }
-    fn setp(&self) -> sdcp::results::NetResult {
-        Ok(vec![])
+    async fn get_param(&mut self, param: DeviceParam) -> Result<ParamValue, NetError> {
+        let parameters = vec![ParamItem::new(
+            Self::param_name(param)?.to_string(),
+            ParamValue::from(""),
+        )];
+        let parameters = self.request(SdcpCommand::Getp, parameters).await?;
+        find_param(&parameters, param)
+            .cloned()
+            .ok_or_else(|| NetError::UnsupportedParameter(param.to_string()))
+    }
+    async fn set_param(&mut self, param: DeviceParam, value: ParamValue) -> NetResult {
+        let parameters = vec![ParamItem::new(Self::param_name(param)?.to_string(), value)];
+        self.request(SdcpCommand::Setp, parameters).await
     }
 }
 
 impl ElectricSocket {
-    pub fn new(name: String, _net_config: NetConfig) -> Result<Self, SmartHouseErrors> {
+    pub fn new(name: String, net_config: NetConfig) -> Result<Self, SmartHouseErrors> {
         if name.len() < DEVICE_IDENTITY_MIN_LENGTH {
             return Err(SmartHouseErrors::InvalidDeviceIdentity);
         }
+        // The handler only sends requests, its own address is never bound
+        let handler = Arc::new(SdcpHandler::new(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            0,
+        )));
         Ok(Self {
             name,
             power_consumption: 0,
             status: false,
+            net_config,
+            handler,
+            readings: VecDeque::new(),
+            energy: 0.0,
+            energy_since: Instant::now(),
         })
     }
     pub fn on(&mut self) {
         self.status = true;
     }
+    // Readings only come from the device, switching off locally does not make one
     pub fn off(&mut self) {
         self.status = false;
         self.power_consumption = 0;
@@ -63,4 +118,84 @@ impl ElectricSocket {
     pub fn status(&self) -> bool {
         self.status
     }
+    pub fn net_config(&self) -> &NetConfig {
+        &self.net_config
+    }
+    // Sockets of a house share the handler of the controller with its timeouts and retries
+    pub fn set_handler(&mut self, handler: Arc<SdcpHandler>) {
+        self.handler = handler;
+    }
+    pub fn readings(&self) -> &VecDeque<PowerReading> {
+        &self.readings
+    }
+
+    // A reading holds until the next one, so the energy between two readings is the
+    // earlier power times the elapsed time; only time after the reset point counts
+    pub fn record_power(&mut self, power_consumption: u32, at: Instant) {
+        if let Some(last) = self.readings.back() {
+            let elapsed = at.saturating_duration_since(last.at.max(self.energy_since));
+            self.energy +=
+                f64::from(last.power_consumption) * elapsed.as_secs_f64() / SECONDS_PER_HOUR;
+        }
+        if self.readings.len() == ELECTRIC_SOCKET_READINGS_CAPACITY {
+            self.readings.pop_front();
+        }
+        self.readings.push_back(PowerReading {
+            at,
+            power_consumption,
+        });
+        self.power_consumption = power_consumption;
+    }
+    pub fn energy_wh(&self) -> f64 {
+        self.energy
+    }
+    pub fn energy_kwh(&self) -> f64 {
+        self.energy / 1000.0
+    }
+    pub fn energy_since(&self) -> Instant {
+        self.energy_since
+    }
+    pub fn reset_energy(&mut self) {
+        self.reset_energy_at(Instant::now());
+    }
+    pub fn reset_energy_at(&mut self, at: Instant) {
+        self.energy = 0.0;
+        self.energy_since = at;
+    }
+
+    fn param_name(param: DeviceParam) -> Result<&'static str, NetError> {
+        match param {
+            DeviceParam::Status | DeviceParam::PowerConsumption => Ok(param.name()),
+            _ => Err(NetError::UnsupportedParameter(param.to_string())),
+        }
+    }
+
+    async fn request(&mut self, command: SdcpCommand, parameters: Vec<ParamItem>) -> NetResult {
+        let address = self.net_config.net_address;
+        let frame = SdcpFrame {
+            protocol: SDCP_PACKET_HEADER.to_string(),
+            id: None,
+            command,
+            parameters,
+            result: SdcpResult::Ok,
+        };
+        let response = self.handler.request(frame, address).await?;
+        self.update(&response.parameters);
+        Ok(response.parameters)
+    }
+
+    // Values of an unexpected type are not the device's state and are skipped
+    fn update(&mut self, parameters: &[ParamItem]) {
+        if let Some(status) =
+            find_param(parameters, DeviceParam::Status).and_then(|value| value.as_bool())
+        {
+            self.status = status;
+        }
+        if let Some(Ok(power_consumption)) = find_param(parameters, DeviceParam::PowerConsumption)
+            .and_then(|value| value.as_integer())
+            .map(u32::try_from)
+        {
+            self.record_power(power_consumption, Instant::now());
+        }
+    }
 }
@@ -1,10 +1,83 @@
-use sdcp::results::NetResult;
+use async_trait::async_trait;
+use std::fmt;
+use std::str::FromStr;
+
+use sdcp::results::{NetError, NetResult};
+use sdcp::{ParamItem, ParamValue, SDCP_PARAM_PWRCON, SDCP_PARAM_STATUS};
+use th_simulator::TH_PARAM_TEMPERATURE;
 
 pub const DEVICE_IDENTITY_MIN_LENGTH: usize = 8;
 
-pub trait SmartDevice {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceParam {
+    Status,
+    PowerConsumption,
+    Temperature,
+}
+
+impl DeviceParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Status => SDCP_PARAM_STATUS,
+            Self::PowerConsumption => SDCP_PARAM_PWRCON,
+            Self::Temperature => TH_PARAM_TEMPERATURE,
+        }
+    }
+}
+
+impl FromStr for DeviceParam {
+    type Err = NetError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_uppercase().as_str() {
+            SDCP_PARAM_STATUS => Ok(Self::Status),
+            SDCP_PARAM_PWRCON => Ok(Self::PowerConsumption),
+            TH_PARAM_TEMPERATURE => Ok(Self::Temperature),
+            _ => Err(NetError::UnsupportedParameter(name.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for DeviceParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// Device I/O is awaited, the cached state behind identity() and info() is not
+#[async_trait]
+pub trait SmartDevice: Send + Sync {
     fn identity(&self) -> &String;
     fn info(&self) -> String;
-    fn getp(&self) -> NetResult;
-    fn setp(&self) -> NetResult;
+    // Reads every parameter of the device and refreshes the cached state
+    async fn refresh(&mut self) -> NetResult;
+    async fn get_param(&mut self, param: DeviceParam) -> Result<ParamValue, NetError>;
+    async fn set_param(&mut self, param: DeviceParam, value: ParamValue) -> NetResult;
+}
+
+// Lets rooms store trait objects the same way they store concrete devices
+#[async_trait]
+impl<T: SmartDevice + ?Sized> SmartDevice for Box<T> {
+    fn identity(&self) -> &String {
+        (**self).identity()
+    }
+    fn info(&self) -> String {
+        (**self).info()
+    }
+    async fn refresh(&mut self) -> NetResult {
+        (**self).refresh().await
+    }
+    async fn get_param(&mut self, param: DeviceParam) -> Result<ParamValue, NetError> {
+        (**self).get_param(param).await
+    }
+    async fn set_param(&mut self, param: DeviceParam, value: ParamValue) -> NetResult {
+        (**self).set_param(param, value).await
+    }
+}
+
+pub(crate) fn find_param(parameters: &[ParamItem], param: DeviceParam) -> Option<&ParamValue> {
+    parameters
+        .iter()
+        .find(|item| item.name.eq(param.name()))
+        .map(|item| &item.value)
 }
@@ -1,16 +1,36 @@
+use async_trait::async_trait;
 use std::fmt::Write;
+use std::time::{Duration, Instant};
 
-use crate::smart_house::smart_room::smart_device::DEVICE_IDENTITY_MIN_LENGTH;
+use crate::smart_house::smart_room::smart_device::{
+    find_param, DeviceParam, DEVICE_IDENTITY_MIN_LENGTH,
+};
 use crate::smart_house::smart_room::SmartDevice;
 use crate::smart_house::SmartHouseErrors;
 
-use sdcp::NetConfig;
+use sdcp::results::{NetError, NetResult};
+use sdcp::udp::devices::{DeviceKey, DeviceSubscription};
+use sdcp::udp::SdcpuHandler;
+use sdcp::{NetConfig, ParamValue};
+
+pub mod temperature;
+
+use self::temperature::{Temperature, TemperatureUnit, TemperatureWindow};
+
+// The simulator reports once a second
+pub const THERMOMETER_TELEMETRY_WAIT: Duration = Duration::from_secs(3);
 
 pub struct Thermometer {
     name: String,
-    temperature: i8,
+    temperature: Temperature,
+    unit: TemperatureUnit,
+    window: TemperatureWindow,
+    net_config: NetConfig,
+    subscription: Option<DeviceSubscription>,
+    last_seen: Option<Instant>,
 }
 
+#[async_trait]
 impl SmartDevice for Thermometer {
     fn identity(&self) -> &String {
         &self.name
@@ -21,33 +41,109 @@ impl SmartDevice for Thermometer {
             info,
             "Thermometer: {} Value: {}",
             &self.name,
-            &self.temperature.to_string()
+            &self.temperature()
         )
         .unwrap();
         info
     }
-    fn getp(&self) -> sdcp::results::NetResult {
-        Ok(vec![])
+    // The thermometer is not asked, its telemetry comes from the SDCPU handler of the controller
+    async fn refresh(&mut self) -> NetResult {
+        let subscription = self.subscription.as_mut().ok_or(NetError::NoTelemetry)?;
+        let device = tokio::time::timeout(
+            THERMOMETER_TELEMETRY_WAIT,
+            subscription.wait_for(|device| {
+                device.as_ref().is_some_and(|device| {
+                    device.is_online()
+                        && find_param(&device.frame.parameters, DeviceParam::Temperature)
+                            .and_then(Temperature::from_param)
+                            .is_some()
+                })
+            }),
+        )
+        .await
+        .map_err(|_| NetError::Timeout)?
+        .map_err(|_| NetError::NoTelemetry)?
+        .clone()
+        .ok_or(NetError::NoTelemetry)?;
+        let temperature = find_param(&device.frame.parameters, DeviceParam::Temperature)
+            .and_then(Temperature::from_param)
+            .ok_or(NetError::NoTelemetry)?;
+        // The same report is not counted twice in the statistics
+        if self.last_seen != Some(device.last_seen) {
+            self.last_seen = Some(device.last_seen);
+            self.set_temperature(temperature);
+        }
+        Ok(device.frame.parameters)
+    }
+    async fn get_param(&mut self, param: DeviceParam) -> Result<ParamValue, NetError> {
+        if param != DeviceParam::Temperature {
+            return Err(NetError::UnsupportedParameter(param.to_string()));
+        }
+        let parameters = self.refresh().await?;
+        find_param(&parameters, param)
+            .cloned()
+            .ok_or(NetError::Timeout)
     }
-    fn setp(&self) -> sdcp::results::NetResult {
-        Ok(vec![])
+    // Nothing on a thermometer can be set
+    async fn set_param(&mut self, param: DeviceParam, _value: ParamValue) -> NetResult {
+        Err(NetError::UnsupportedParameter(param.to_string()))
     }
 }
 
 impl Thermometer {
-    pub fn new(name: String, _net_config: NetConfig) -> Result<Self, SmartHouseErrors> {
+    pub fn new(name: String, net_config: NetConfig) -> Result<Self, SmartHouseErrors> {
         if name.len() < DEVICE_IDENTITY_MIN_LENGTH {
             return Err(SmartHouseErrors::InvalidDeviceIdentity);
         }
         Ok(Self {
             name,
-            temperature: 0,
+            temperature: Temperature::celsius(0.0),
+            unit: TemperatureUnit::default(),
+            window: TemperatureWindow::default(),
+            net_config,
+            subscription: None,
+            last_seen: None,
         })
     }
-    pub fn temperature(&self) -> i8 {
-        self.temperature
+    // Readings and statistics are reported in the unit of the thermometer
+    pub fn temperature(&self) -> Temperature {
+        self.temperature.to(self.unit)
     }
-    pub fn set_temperature(&mut self, temperature: i8) {
+    pub fn set_temperature(&mut self, temperature: Temperature) {
         self.temperature = temperature;
+        self.window.push(temperature);
+    }
+    pub fn unit(&self) -> TemperatureUnit {
+        self.unit
+    }
+    pub fn set_unit(&mut self, unit: TemperatureUnit) {
+        self.unit = unit;
+    }
+    pub fn set_window_size(&mut self, size: usize) {
+        self.window.set_size(size);
+    }
+    pub fn min_temperature(&self) -> Option<Temperature> {
+        self.window
+            .min()
+            .map(|temperature| temperature.to(self.unit))
+    }
+    pub fn max_temperature(&self) -> Option<Temperature> {
+        self.window
+            .max()
+            .map(|temperature| temperature.to(self.unit))
+    }
+    pub fn average_temperature(&self) -> Option<Temperature> {
+        self.window
+            .average()
+            .map(|temperature| temperature.to(self.unit))
+    }
+    pub fn net_config(&self) -> &NetConfig {
+        &self.net_config
+    }
+    // Telemetry is taken from the frames the handler receives from the thermometer address
+    pub fn attach(&mut self, handler: &SdcpuHandler) {
+        self.subscription =
+            Some(handler.subscribe(DeviceKey::Address(self.net_config.net_address)));
+        self.last_seen = None;
     }
 }
@@ -0,0 +1,164 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+
+use sdcp::ParamValue;
+
+const KELVIN_OFFSET: f64 = 273.15;
+
+pub const TEMPERATURE_WINDOW_SIZE: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl fmt::Display for TemperatureUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Celsius => write!(f, "°C"),
+            Self::Fahrenheit => write!(f, "°F"),
+            Self::Kelvin => write!(f, "K"),
+        }
+    }
+}
+
+// Comparison goes through one scale, so 0 °C equals 273.15 K
+#[derive(Debug, Clone, Copy)]
+pub struct Temperature {
+    value: f64,
+    unit: TemperatureUnit,
+}
+
+impl Temperature {
+    pub fn new(value: f64, unit: TemperatureUnit) -> Self {
+        Self { value, unit }
+    }
+    pub fn celsius(value: f64) -> Self {
+        Self::new(value, TemperatureUnit::Celsius)
+    }
+    pub fn fahrenheit(value: f64) -> Self {
+        Self::new(value, TemperatureUnit::Fahrenheit)
+    }
+    pub fn kelvin(value: f64) -> Self {
+        Self::new(value, TemperatureUnit::Kelvin)
+    }
+
+    // SDCPU telemetry carries TEMPERATURE in Celsius, as a number or its text
+    pub fn from_param(value: &ParamValue) -> Option<Self> {
+        let value = match value {
+            ParamValue::String(value) => value.trim().parse().ok()?,
+            value => value.as_float()?,
+        };
+        value.is_finite().then(|| Self::celsius(value))
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+    pub fn unit(&self) -> TemperatureUnit {
+        self.unit
+    }
+
+    pub fn to(&self, unit: TemperatureUnit) -> Self {
+        let celsius = match self.unit {
+            TemperatureUnit::Celsius => self.value,
+            TemperatureUnit::Fahrenheit => (self.value - 32.0) * 5.0 / 9.0,
+            TemperatureUnit::Kelvin => self.value - KELVIN_OFFSET,
+        };
+        let value = match unit {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            TemperatureUnit::Kelvin => celsius + KELVIN_OFFSET,
+        };
+        Self::new(value, unit)
+    }
+}
+
+impl PartialEq for Temperature {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Temperature {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.to(TemperatureUnit::Kelvin)
+            .value
+            .partial_cmp(&other.to(TemperatureUnit::Kelvin).value)
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} {}", self.value, self.unit)
+    }
+}
+
+// Keeps the last readings only, older ones leave the window as new ones arrive
+#[derive(Debug, Clone)]
+pub struct TemperatureWindow {
+    size: usize,
+    readings: VecDeque<Temperature>,
+}
+
+impl TemperatureWindow {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            readings: VecDeque::new(),
+        }
+    }
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size.max(1);
+        while self.readings.len() > self.size {
+            self.readings.pop_front();
+        }
+    }
+    pub fn push(&mut self, temperature: Temperature) {
+        if self.readings.len() == self.size {
+            self.readings.pop_front();
+        }
+        self.readings
+            .push_back(temperature.to(TemperatureUnit::Celsius));
+    }
+    pub fn len(&self) -> usize {
+        self.readings.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.readings.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.readings.clear();
+    }
+
+    // Statistics are in Celsius, convert them with to() when needed
+    pub fn min(&self) -> Option<Temperature> {
+        self.readings
+            .iter()
+            .copied()
+            .min_by(|op1, op2| op1.value.total_cmp(&op2.value))
+    }
+    pub fn max(&self) -> Option<Temperature> {
+        self.readings
+            .iter()
+            .copied()
+            .max_by(|op1, op2| op1.value.total_cmp(&op2.value))
+    }
+    pub fn average(&self) -> Option<Temperature> {
+        if self.readings.is_empty() {
+            return None;
+        }
+        let sum: f64 = self.readings.iter().map(|reading| reading.value).sum();
+        Some(Temperature::celsius(sum / self.readings.len() as f64))
+    }
+}
+
+impl Default for TemperatureWindow {
+    fn default() -> Self {
+        Self::new(TEMPERATURE_WINDOW_SIZE)
+    }
+}
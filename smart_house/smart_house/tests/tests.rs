@@ -1,13 +1,30 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use sdcp::NetConfig;
+use sdcp::codec::SdcpFrameCodec;
+use sdcp::results::{FrameResult, NetError};
+use sdcp::udp::{make_datagram, make_telemetry_frame, SdcpuHandler, SdcpuOptions};
+use sdcp::{
+    NetConfig, ParamItem, ParamValue, SdcpCommand, SdcpHandler, SdcpServerHandle,
+    SDCP_PARAM_PWRCON, SDCP_PARAM_STATUS,
+};
+use smart_house_lib::clever_house::CleverHouse;
 use smart_house_lib::smart_house::{
+    clever_room::{clever_device::CleverDevice, CleverRoom},
     smart_room::{
-        electric_socket::ElectricSocket, smart_device::SmartDevice, thermometer::Thermometer,
+        electric_socket::ElectricSocket,
+        smart_device::{DeviceParam, SmartDevice},
+        thermometer::{
+            temperature::{Temperature, TemperatureUnit},
+            Thermometer,
+        },
         SmartRoom,
     },
     SmartHouse,
 };
+use th_simulator::TH_PARAM_TEMPERATURE;
 
 #[test]
 fn test_electric_socket_off_report() {
@@ -24,7 +41,7 @@ fn test_electric_socket_off_report() {
     );
     let device_info = device.info();
     assert_eq!(
-        "Electric socket: Electric socket #1 State: OFF",
+        "Electric socket: Electric socket #1 State: OFF Energy: 0.000 kWh",
         device_info
     );
 }
@@ -44,7 +61,7 @@ fn test_electric_socket_on_report() {
     device.on();
     let device_info = device.info();
     assert_eq!(
-        "Electric socket: Electric socket #1 State: ON Consumption power: 0",
+        "Electric socket: Electric socket #1 State: ON Consumption power: 0 Energy: 0.000 kWh",
         device_info
     );
 }
@@ -62,7 +79,7 @@ fn test_thermometer_initial_report() {
         },
     );
     let device_info = device.info();
-    assert_eq!("Thermometer: Thermometer #1 Value: 0", device_info);
+    assert_eq!("Thermometer: Thermometer #1 Value: 0.00 °C", device_info);
 }
 #[test]
 fn test_thermometer_onaction_report() {
@@ -77,9 +94,9 @@ fn test_thermometer_onaction_report() {
             Err(e) => panic!("{:?}", e),
         },
     );
-    device.set_temperature(25);
+    device.set_temperature(Temperature::celsius(25.0));
     let device_info = device.info();
-    assert_eq!("Thermometer: Thermometer #1 Value: 25", device_info);
+    assert_eq!("Thermometer: Thermometer #1 Value: 25.00 °C", device_info);
 }
 #[test]
 fn test_smart_room_init_report() {
@@ -129,7 +146,7 @@ fn test_smart_room_onaction_report() {
 
     let room_info = room.info();
     assert_eq!(
-        "Electric socket: Electric socket #1 State: OFF\nThermometer: Thermometer #1 Value: 0\n",
+        "Electric socket: Electric socket #1 State: OFF Energy: 0.000 kWh\nThermometer: Thermometer #1 Value: 0.00 °C\n",
         room_info
     );
 }
@@ -228,7 +245,7 @@ fn test_smart_house_onaction_report() {
     }
 
     let info = house.info();
-    assert_eq!("House Smart House\nRoom: Smart Room #1\nElectric socket: Electric socket #1 State: OFF\nThermometer: Thermometer #1 Value: 0\nRoom: Smart Room #2\nElectric socket: Electric socket #1 State: OFF\nThermometer: Thermometer #1 Value: 0\n", info);
+    assert_eq!("House Smart House\nRoom: Smart Room #1\nElectric socket: Electric socket #1 State: OFF Energy: 0.000 kWh\nThermometer: Thermometer #1 Value: 0.00 °C\nRoom: Smart Room #2\nElectric socket: Electric socket #1 State: OFF Energy: 0.000 kWh\nThermometer: Thermometer #1 Value: 0.00 °C\n", info);
 
     let info = house.info_rooms();
     assert_eq!("Room: Smart Room #1\nRoom: Smart Room #2\n", info);
@@ -247,7 +264,7 @@ fn test_smart_house_onaction_report() {
     }
 
     let info = house.info();
-    assert_eq!("House Smart House\nRoom: Smart Room #1\nThermometer: Thermometer #1 Value: 0\nRoom: Smart Room #2\nElectric socket: Electric socket #1 State: OFF\nThermometer: Thermometer #1 Value: 0\n", info);
+    assert_eq!("House Smart House\nRoom: Smart Room #1\nThermometer: Thermometer #1 Value: 0.00 °C\nRoom: Smart Room #2\nElectric socket: Electric socket #1 State: OFF Energy: 0.000 kWh\nThermometer: Thermometer #1 Value: 0.00 °C\n", info);
 
     let room = house.rem("Smart Room #1");
     match room {
@@ -259,3 +276,232 @@ fn test_smart_house_onaction_report() {
         None => panic!("Room not found"),
     }
 }
+
+#[test]
+fn test_clever_house_onaction_report() {
+    let mut house = CleverHouse::new(String::from("Clever House")).unwrap();
+    let mut room = CleverRoom::new(String::from("Clever Room #1")).unwrap();
+
+    let net_config = NetConfig::new(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        55000,
+    ));
+    let device = ElectricSocket::new(String::from("Electric socket #1"), net_config).unwrap();
+    room.add(CleverDevice::ElecticSocket(device)).unwrap();
+
+    let net_config = NetConfig::new(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        55000,
+    ));
+    let device = Thermometer::new(String::from("Thermometer #1"), net_config).unwrap();
+    room.add(CleverDevice::Thermometer(device)).unwrap();
+
+    if let Some(CleverDevice::ElecticSocket(device)) = room.get_mut("Electric socket #1") {
+        device.on();
+    }
+    house.add(room).unwrap();
+
+    assert_eq!(
+        house.info(),
+        "House 'Clever House'\n\
+         Electric socket: Electric socket #1 State: ON Consumption power: 0 Energy: 0.000 kWh\n\
+         Thermometer: Thermometer #1 Value: 0.00 °C\n"
+    );
+    assert_eq!(
+        house
+            .device_status("Clever Room #1", "Thermometer #1")
+            .unwrap(),
+        "Room Clever Room #1 Device status Thermometer: Thermometer #1 Value: 0.00 °C"
+    );
+}
+
+#[test]
+fn test_electric_socket_energy() {
+    let net_config = NetConfig::new(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        55000,
+    ));
+    let mut device = ElectricSocket::new(String::from("Electric socket #1"), net_config).unwrap();
+    let start = Instant::now();
+    device.reset_energy_at(start);
+
+    // 1000 W for half an hour, then 2000 W for a quarter of an hour
+    device.record_power(1000, start);
+    device.record_power(2000, start + Duration::from_secs(1800));
+    device.record_power(0, start + Duration::from_secs(2700));
+    assert!((device.energy_wh() - 1000.0).abs() < 1e-9);
+    assert!((device.energy_kwh() - 1.0).abs() < 1e-12);
+    assert_eq!(device.readings().len(), 3);
+    assert_eq!(device.power_consumption(), 0);
+
+    // Only the time after the reset point counts
+    device.record_power(3000, start + Duration::from_secs(3600));
+    device.reset_energy_at(start + Duration::from_secs(5400));
+    device.record_power(0, start + Duration::from_secs(7200));
+    assert!((device.energy_wh() - 1500.0).abs() < 1e-9);
+
+    device.on();
+    device.off();
+    assert_eq!(device.readings().len(), 5);
+    assert_eq!(device.power_consumption(), 0);
+    assert_eq!(
+        device.info(),
+        "Electric socket: Electric socket #1 State: OFF Energy: 1.500 kWh"
+    );
+}
+
+#[test]
+fn test_thermometer_units_and_statistics() {
+    let net_config = NetConfig::new(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        55000,
+    ));
+    let mut device = Thermometer::new(String::from("Thermometer #1"), net_config).unwrap();
+    assert_eq!(device.average_temperature(), None);
+
+    assert_eq!(Temperature::celsius(100.0), Temperature::fahrenheit(212.0));
+    assert_eq!(Temperature::celsius(-273.15), Temperature::kelvin(0.0));
+    assert_eq!(
+        Temperature::from_param(&ParamValue::String(String::from("10.25"))),
+        Some(Temperature::celsius(10.25))
+    );
+    assert_eq!(
+        Temperature::from_param(&ParamValue::Integer(300)),
+        Some(Temperature::celsius(300.0))
+    );
+    assert_eq!(Temperature::from_param(&ParamValue::Bool(true)), None);
+
+    device.set_window_size(3);
+    for value in [10.25, 150.5, 20.0, 11.75] {
+        device.set_temperature(Temperature::celsius(value));
+    }
+    assert_eq!(device.min_temperature(), Some(Temperature::celsius(11.75)));
+    assert_eq!(device.max_temperature(), Some(Temperature::celsius(150.5)));
+    assert_eq!(
+        device.average_temperature(),
+        Some(Temperature::celsius(60.75))
+    );
+    assert_eq!(device.info(), "Thermometer: Thermometer #1 Value: 11.75 °C");
+
+    device.set_unit(TemperatureUnit::Fahrenheit);
+    assert_eq!(device.temperature().unit(), TemperatureUnit::Fahrenheit);
+    assert_eq!(device.info(), "Thermometer: Thermometer #1 Value: 53.15 °F");
+}
+
+async fn start_socket_server() -> SdcpServerHandle {
+    let handler = SdcpHandler::new(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0));
+    handler
+        .bind(|frame: FrameResult| async move {
+            let mut frame = frame.unwrap();
+            if frame.command == SdcpCommand::Getp {
+                frame.parameters = vec![
+                    ParamItem::new(SDCP_PARAM_STATUS.to_string(), ParamValue::Bool(true)),
+                    ParamItem::new(SDCP_PARAM_PWRCON.to_string(), ParamValue::Integer(120)),
+                ];
+            }
+            frame
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_electric_socket_network_state() {
+    let server = start_socket_server().await;
+    let mut device = ElectricSocket::new(
+        String::from("Electric socket #1"),
+        NetConfig::new(server.local_address()),
+    )
+    .unwrap();
+    let mut handler = SdcpHandler::new(server.local_address());
+    handler.set_timeouts(Some(Duration::from_secs(5)), Some(Duration::from_secs(5)));
+    device.set_handler(Arc::new(handler));
+    device.refresh().await.unwrap();
+    assert!(device.status());
+    assert_eq!(device.power_consumption(), 120);
+
+    let parameters = device
+        .set_param(DeviceParam::Status, ParamValue::Bool(false))
+        .await
+        .unwrap();
+    assert_eq!(parameters[0].value, ParamValue::Bool(false));
+    assert!(!device.status());
+
+    assert_eq!(
+        device.get_param(DeviceParam::Status).await.unwrap(),
+        ParamValue::Bool(true)
+    );
+    assert!(matches!(
+        device.get_param(DeviceParam::Temperature).await,
+        Err(NetError::UnsupportedParameter(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_thermometer_network_state() {
+    let shared_frame = Arc::new(Mutex::new(Box::new(make_telemetry_frame(vec![]))));
+    let handler = SdcpuHandler::new(
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0),
+        shared_frame,
+        SdcpuOptions::default(),
+    )
+    .await
+    .unwrap();
+    let target = handler.local_address();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+
+    // The thermometer is addressed by the device itself, not by the listening socket
+    let mut device =
+        Thermometer::new(String::from("Thermometer #1"), NetConfig::new(address)).unwrap();
+    assert!(matches!(device.refresh().await, Err(NetError::NoTelemetry)));
+    device.attach(&handler);
+
+    // Another thermometer reporting to the same receiver is not mixed in
+    let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sender = thread::spawn(move || {
+        let datagram = |temperature| {
+            let frame = make_telemetry_frame(vec![ParamItem::new(
+                TH_PARAM_TEMPERATURE.to_string(),
+                ParamValue::Float(temperature),
+            )]);
+            make_datagram(frame, &mut SdcpFrameCodec::default()).unwrap()
+        };
+        for _ in 0..20 {
+            other.send_to(&datagram(99.0), target).unwrap();
+            socket.send_to(&datagram(21.75), target).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+    assert_eq!(
+        device.get_param(DeviceParam::Temperature).await.unwrap(),
+        ParamValue::Float(21.75)
+    );
+    assert_eq!(device.temperature(), Temperature::celsius(21.75));
+    assert!(device
+        .set_param(DeviceParam::Temperature, ParamValue::Float(0.0))
+        .await
+        .is_err());
+    sender.join().unwrap();
+    handler.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_smart_room_refresh() {
+    let server = start_socket_server().await;
+    let mut room = SmartRoom::new(String::from("Smart Room #1")).unwrap();
+    for name in ["Electric socket #1", "Electric socket #2"] {
+        let device =
+            ElectricSocket::new(name.to_string(), NetConfig::new(server.local_address())).unwrap();
+        room.add(Box::new(device)).unwrap();
+    }
+
+    let results = room.refresh().await;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+    assert_eq!(
+        room.info(),
+        "Electric socket: Electric socket #1 State: ON Consumption power: 120 Energy: 0.000 kWh\n\
+         Electric socket: Electric socket #2 State: ON Consumption power: 120 Energy: 0.000 kWh\n"
+    );
+}
@@ -1,11 +1,17 @@
 use std::io::{self, Write};
 use std::thread;
 use std::{
-    net::{SocketAddr, UdpSocket},
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
     time::Duration,
 };
 
-use sdcpu::{make_packet, ParamItem, SdcpuFrame, SDCPU_PACKET_HEADER};
+use sdcp::codec::SdcpFrameCodec;
+use sdcp::udp::auth::SdcpuSigner;
+use sdcp::udp::discovery::{
+    discovery_reply, discovery_socket, DeviceInfo, SDCP_DISCOVERY_PORT, SDCP_TYPE_THERMOMETER,
+};
+use sdcp::udp::{make_datagram, make_telemetry_frame, SDCPU_MAX_DATAGRAM_SIZE};
+use sdcp::{ParamItem, ParamValue};
 
 use th_simulator::TH_PARAM_TEMPERATURE;
 
@@ -15,24 +21,45 @@ fn main() {
     println!("Адрес приёмника: {}", address);
 
     let target = address.parse::<SocketAddr>().unwrap();
+    // Datagrams are signed when a device name and a pre-shared key follow the address
+    let device = args.next();
+    let mut signer = match (device.clone(), args.next()) {
+        (Some(device), Some(key)) => Some(SdcpuSigner::new(device, key.into_bytes())),
+        _ => None,
+    };
 
-    let address = "127.0.0.1:4000";
+    // Any free port, so several simulators can run on one host; discovery reports the real one
+    let address = "127.0.0.1:0";
     let socket = UdpSocket::bind(address)
         .expect("Ошибка привязки к адресу, возможно требуемый порт уже занят");
+    let info = DeviceInfo::new(
+        device.unwrap_or_else(|| String::from("TH-1")),
+        SDCP_TYPE_THERMOMETER.to_string(),
+        socket.local_addr().unwrap(),
+        vec![TH_PARAM_TEMPERATURE.to_string()],
+    );
+    let discovery_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), SDCP_DISCOVERY_PORT);
+    match discovery_socket(discovery_address) {
+        Ok(discovery) => {
+            thread::spawn(move || answer_probes(discovery, info));
+        }
+        Err(error) => println!("Обнаружение недоступно: {error}"),
+    }
+    let mut codec = SdcpFrameCodec::default();
     let mut i = 10.0;
 
     loop {
         i += 0.25;
-        let frame: SdcpuFrame = SdcpuFrame {
-            protocol: SDCPU_PACKET_HEADER.to_string(),
-            parameters: vec![ParamItem::new(
-                TH_PARAM_TEMPERATURE.to_string(),
-                i.to_string(),
-            )],
-        };
-        let packet = make_packet(&frame);
-        let data = packet.as_bytes();
-        let result = socket.send_to(data, target);
+        let frame = make_telemetry_frame(vec![ParamItem::new(
+            TH_PARAM_TEMPERATURE.to_string(),
+            ParamValue::Float(i),
+        )]);
+        let datagram = match signer.as_mut() {
+            Some(signer) => signer.sign(&frame),
+            None => make_datagram(frame, &mut codec),
+        }
+        .expect("Кадр превышает максимальный размер");
+        let result = socket.send_to(&datagram, target);
         match result {
             Ok(_) => {
                 print!("Температура устройства {i}\r");
@@ -43,3 +70,13 @@ fn main() {
         thread::sleep(Duration::from_secs(1));
     }
 }
+
+fn answer_probes(socket: UdpSocket, info: DeviceInfo) {
+    let mut codec = SdcpFrameCodec::default();
+    let mut datagram = [0; SDCPU_MAX_DATAGRAM_SIZE];
+    while let Ok((length, sender)) = socket.recv_from(&mut datagram) {
+        if let Some(reply) = discovery_reply(&datagram[..length], &info, &mut codec) {
+            let _ = socket.send_to(&reply, sender);
+        }
+    }
+}